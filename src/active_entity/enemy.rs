@@ -2,13 +2,10 @@ use crate::game::EntityAction;
 use crate::map;
 use crate::map::MapItem;
 
-pub fn get_enemy_action<const DIMX: usize, const DIMY: usize>(
-    [pos_x, pos_y]: [usize; 2],
-    map: &map::Map<DIMX, DIMY>,
-) -> EntityAction {
+pub fn get_enemy_action([pos_x, pos_y]: [usize; 2], map: &map::Map) -> EntityAction {
     if let Ok([hero_x, hero_y]) = find_hero_on_map(map) {
-        let [diff_x, diff_y]: [i32; 2] =
-            [hero_x as i32 - pos_x as i32, hero_y as i32 - pos_y as i32];
+        // On a torus this chases the hero the short way around the edges.
+        let [diff_x, diff_y]: [i32; 2] = map.displacement([pos_x, pos_y], [hero_x, hero_y]);
         if diff_x.abs() > diff_y.abs() {
             if diff_x < 0 {
                 return EntityAction::MoveLeft;
@@ -27,12 +24,10 @@ pub fn get_enemy_action<const DIMX: usize, const DIMY: usize>(
     }
 }
 
-fn find_hero_on_map<const DIMX: usize, const DIMY: usize>(
-    map: &map::Map<DIMX, DIMY>,
-) -> Result<[usize; 2], ()> {
-    let working_map = map.map.expect("Map should exist!");
-    for rowidx in 0..DIMX {
-        for colidx in 0..DIMY {
+fn find_hero_on_map(map: &map::Map) -> Result<[usize; 2], ()> {
+    let working_map = map.map.as_ref().expect("Map should exist!");
+    for rowidx in 0..map.dimx() {
+        for colidx in 0..map.dimy() {
             if working_map[rowidx][colidx] == MapItem::HeroEntity {
                 return Ok([rowidx, colidx]);
             }
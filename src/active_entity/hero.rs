@@ -1,6 +1,7 @@
 use crate::game::{EntityAction, GameState};
-use crate::map::{Map, MapItem};
+use crate::map::{Map, MapGrid, MapItem};
 use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
 use std::cmp::min;
 use std::collections::VecDeque;
 
@@ -10,21 +11,20 @@ struct ComparableMapNode {
     est_cost: f32,
 }
 
-pub fn get_hero_action<const DIMX: usize, const DIMY: usize>(
+pub fn get_hero_action(
     position: [usize; 2],
-    map: &Map<DIMX, DIMY>,
+    map: &Map,
     game_state: &GameState,
+    rng: &mut StdRng,
 ) -> EntityAction {
-    let action = get_pathfinding_action(position, game_state, map);
+    let action = get_pathfinding_action(position, game_state, map, rng);
 
     return action;
 }
 
-fn find_goal<const DIMX: usize, const DIMY: usize>(
-    map: [[MapItem; DIMY]; DIMX],
-) -> Option<[usize; 2]> {
-    for pos_x in 0..DIMX {
-        for pos_y in 0..DIMY {
+fn find_goal(map: &MapGrid) -> Option<[usize; 2]> {
+    for pos_x in 0..map.len() {
+        for pos_y in 0..map[pos_x].len() {
             if map[pos_x][pos_y] == MapItem::Goal {
                 return Some([pos_x, pos_y]);
             }
@@ -33,12 +33,10 @@ fn find_goal<const DIMX: usize, const DIMY: usize>(
     return None;
 }
 
-fn find_enemies<const DIMX: usize, const DIMY: usize>(
-    map: [[MapItem; DIMY]; DIMX],
-) -> Vec<[usize; 2]> {
+fn find_enemies(map: &MapGrid) -> Vec<[usize; 2]> {
     let mut enemies = Vec::new();
-    for pos_x in 0..DIMX {
-        for pos_y in 0..DIMY {
+    for pos_x in 0..map.len() {
+        for pos_y in 0..map[pos_x].len() {
             if map[pos_x][pos_y] == MapItem::EnemyEntity {
                 enemies.push([pos_x, pos_y]);
             }
@@ -47,14 +45,15 @@ fn find_enemies<const DIMX: usize, const DIMY: usize>(
     return enemies;
 }
 
-fn precompute_distance_to_goal<const DIMX: usize, const DIMY: usize>(
-    map: &Map<DIMX, DIMY>,
-) -> [[Option<u32>; DIMY]; DIMX] {
-    let mut distance_map: [[Option<u32>; DIMY]; DIMX] = [[None; DIMY]; DIMX];
-    let goal_pos = find_goal(map.map.expect("The map must be initialized!"))
+fn precompute_distance_to_goal(map: &Map) -> Vec<Vec<Option<u32>>> {
+    let mut distance_map: Vec<Vec<Option<u32>>> = vec![vec![None; map.dimy()]; map.dimx()];
+    let goal_pos = find_goal(map.map.as_ref().expect("The map must be initialized!"))
         .expect("The map must contain a goal!");
     let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+    // Tracks what is already in the frontier, so large maps don't pay for a linear search.
+    let mut queued: Vec<Vec<bool>> = vec![vec![false; map.dimy()]; map.dimx()];
     for neighbor in map.get_empty_neighbors(goal_pos) {
+        queued[neighbor[0]][neighbor[1]] = true;
         frontier.push_back(neighbor);
     }
     distance_map[goal_pos[0]][goal_pos[1]] = Some(0);
@@ -69,7 +68,8 @@ fn precompute_distance_to_goal<const DIMX: usize, const DIMY: usize>(
                         lowest_neighbor_value = neighbor_val;
                         lowest_neighbor = Some([*n_x, *n_y]);
                     }
-                } else if !frontier.contains(&[*n_x, *n_y]) {
+                } else if !queued[*n_x][*n_y] {
+                    queued[*n_x][*n_y] = true;
                     frontier.push_back([*n_x, *n_y]);
                 }
             }
@@ -97,16 +97,17 @@ fn precompute_distance_to_goal<const DIMX: usize, const DIMY: usize>(
     return distance_map;
 }
 
-fn precompute_distance_to_enemy<const DIMX: usize, const DIMY: usize>(
-    map: &Map<DIMX, DIMY>,
-) -> [[Option<u32>; DIMY]; DIMX] {
-    let mut distance_map: [[Option<u32>; DIMY]; DIMX] = [[None; DIMY]; DIMX];
-    let enemy_positions = find_enemies(map.map.expect("The map must be initialized!"));
+fn precompute_distance_to_enemy(map: &Map) -> Vec<Vec<Option<u32>>> {
+    let mut distance_map: Vec<Vec<Option<u32>>> = vec![vec![None; map.dimy()]; map.dimx()];
+    let enemy_positions = find_enemies(map.map.as_ref().expect("The map must be initialized!"));
     let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+    let mut queued: Vec<Vec<bool>> = vec![vec![false; map.dimy()]; map.dimx()];
     for enemy in &enemy_positions {
         distance_map[enemy[0]][enemy[1]] = Some(0);
         for neighbor in map.get_empty_neighbors(*enemy) {
-            if distance_map[neighbor[0]][neighbor[1]].is_none() && !frontier.contains(&neighbor) {
+            if distance_map[neighbor[0]][neighbor[1]].is_none() && !queued[neighbor[0]][neighbor[1]]
+            {
+                queued[neighbor[0]][neighbor[1]] = true;
                 frontier.push_back(neighbor);
             }
         }
@@ -122,7 +123,8 @@ fn precompute_distance_to_enemy<const DIMX: usize, const DIMY: usize>(
                         lowest_neighbor_value = neighbor_val;
                         lowest_neighbor = Some([*n_x, *n_y]);
                     }
-                } else if !frontier.contains(&[*n_x, *n_y]) {
+                } else if !queued[*n_x][*n_y] {
+                    queued[*n_x][*n_y] = true;
                     frontier.push_back([*n_x, *n_y]);
                 }
             }
@@ -146,12 +148,10 @@ fn precompute_distance_to_enemy<const DIMX: usize, const DIMY: usize>(
     return distance_map;
 }
 
-fn print_distance_map<const DIMX: usize, const DIMY: usize>(
-    distance_map: [[Option<u32>; DIMY]; DIMX],
-) {
+fn print_distance_map(distance_map: &Vec<Vec<Option<u32>>>) {
     let mut display_string = String::new();
-    for colidx in 0..DIMY {
-        for rowidx in 0..DIMX {
+    for colidx in 0..distance_map[0].len() {
+        for rowidx in 0..distance_map.len() {
             let map_item = distance_map[rowidx][colidx];
             if map_item.is_some() {
                 let val = map_item.unwrap();
@@ -171,12 +171,14 @@ fn print_distance_map<const DIMX: usize, const DIMY: usize>(
     println!("{}", display_string);
 }
 
-fn get_pathfinding_action<const DIMX: usize, const DIMY: usize>(
+fn get_pathfinding_action(
     starting_position: [usize; 2],
     game_state: &GameState,
-    map: &Map<DIMX, DIMY>,
+    map: &Map,
+    rng: &mut StdRng,
 ) -> EntityAction {
-    let mut frontier: VecDeque<ComparableMapNode> = VecDeque::with_capacity(DIMX * DIMY);
+    let mut frontier: VecDeque<ComparableMapNode> =
+        VecDeque::with_capacity(map.dimx() * map.dimy());
     frontier.push_back(ComparableMapNode {
         node: starting_position,
         est_cost: 0.,
@@ -208,7 +210,7 @@ fn get_pathfinding_action<const DIMX: usize, const DIMY: usize>(
     let mut least_neighbor = None;
     let mut least_neighbor_value: f64 = u32::MAX as f64;
     let mut neighbors = map.get_empty_neighbors(starting_position);
-    neighbors.shuffle(&mut rand::rng());
+    neighbors.shuffle(rng);
     for [n_x, n_y] in neighbors {
         let neighbor_value: f64 = f64::sqrt(goal_distance_map[n_x][n_y].unwrap_or(u32::MAX) as f64)
             - f64::min(
@@ -222,8 +224,7 @@ fn get_pathfinding_action<const DIMX: usize, const DIMY: usize>(
     }
 
     if let Some([n_x, n_y]) = least_neighbor {
-        let [p_x, p_y] = starting_position;
-        let (d_x, d_y) = (n_x as i32 - p_x as i32, n_y as i32 - p_y as i32);
+        let [d_x, d_y] = map.displacement(starting_position, [n_x, n_y]);
         if d_x < 0 {
             return EntityAction::MoveLeft;
         };
@@ -1,8 +1,10 @@
 
 var game;
+var gameInterval;
 var recorder;
 var stream;
 var link;
+var replaying = false;
 
 addEventListener("TrunkApplicationStarted", (event) => {
 	beginWasmGame();
@@ -26,19 +28,288 @@ async function beginWasmGame() {
 	let button = document.getElementById("flatland_restart");
 	button.disabled = true;
 	link.disabled = true;
-	game = wasmBindings.WasmGame.new(0.25, 20, 5);
+	showWasmError(null);
+	setReplayControls(false);
+	let params = new URLSearchParams(window.location.search);
+	let seed_param = params.get("seed");
+	let options;
+	try {
+		options = wasmGameOptionsFromParams(params);
+	} catch (error) {
+		showWasmError(error);
+		return;
+	}
+	if (params.get("level") !== null) {
+		let response = await fetch("levels/" + params.get("level") + ".txt");
+		let level_text = await response.text();
+		try {
+			game = wasmBindings.WasmGame.from_text(level_text, 5, seed_param === null ? undefined : BigInt(seed_param));
+		} catch (error) {
+			showWasmError(error);
+			return;
+		}
+	} else if (params.get("tiled") !== null) {
+		let response = await fetch("levels/" + params.get("tiled") + ".tmj");
+		let level_json = await response.text();
+		try {
+			game = wasmBindings.WasmGame.from_tiled(level_json, 5, seed_param === null ? undefined : BigInt(seed_param));
+		} catch (error) {
+			showWasmError(error);
+			return;
+		}
+	} else {
+		try {
+			game = wasmBindings.WasmGame.new(options);
+		} catch (error) {
+			showWasmError(error);
+			return;
+		}
+	}
+	try {
+		if (params.get("topology") !== null && (params.get("level") !== null || params.get("tiled") !== null)) {
+			// Levels aren't generated, so their topology is set on the loaded game.
+			game.set_topology(params.get("topology"));
+		}
+		if (params.get("resolution") !== null) {
+			game.set_action_resolution(params.get("resolution"));
+		}
+		if (params.get("boundary") !== null) {
+			game.set_boundary(params.get("boundary"));
+		}
+	} catch (error) {
+		showWasmError(error);
+		return;
+	}
+	let report = JSON.parse(game.difficulty_report());
+	let info = "Seed " + game.seed() + ", obstacle coverage " + (game.obstacle_coverage() * 100).toFixed(1) + "%";
+	if (report !== null) {
+		info += ", path length " + (report.path_length ?? "unreachable")
+			+ " (" + (report.path_to_straight_line_ratio ?? 0).toFixed(2) + "x straight line)"
+			+ ", " + report.chokepoints + " chokepoints"
+			+ ", largest region " + report.largest_region_size
+			+ ", " + report.enemies_near_path + " enemies near the path"
+			+ ", " + report.unreachable_cells + " unreachable cells";
+	}
+	document.getElementById("flatland_info").textContent = info;
+	console.log("Flatland " + info);
+	runWasmGame();
+}
+
+// Build the generation options from the page's URL parameters. Throws if a parameter names
+// something the game doesn't know.
+function wasmGameOptionsFromParams(params) {
+	let seed_param = params.get("seed");
+	let width = parseInt(params.get("width") ?? "64");
+	let height = parseInt(params.get("height") ?? "64");
+	let options = new wasmBindings.WasmGameOptions(width, height, 0.25, 20, 5);
+	if (seed_param !== null) {
+		options.set_seed(BigInt(seed_param));
+	}
+	if (params.get("enemy_distance") !== null) {
+		options.set_min_hero_enemy_distance(parseInt(params.get("enemy_distance")));
+	}
+	if (params.get("goal_distance") !== null) {
+		options.set_min_hero_goal_distance(parseInt(params.get("goal_distance")));
+	}
+	if (params.get("goal") !== null) {
+		options.set_goal_placement(params.get("goal"));
+	}
+	if (params.get("topology") !== null) {
+		options.set_topology(params.get("topology"));
+	}
+	if (params.get("symmetry") !== null) {
+		options.set_symmetry(params.get("symmetry"), params.get("spawns") ?? "swap");
+	}
+	if (params.get("enemies") === "spread") {
+		options.use_spread_enemies(parseFloat(params.get("spacing") ?? "8"));
+	} else if (params.get("enemies") === "guard") {
+		options.use_goal_guard_enemies(parseInt(params.get("radius") ?? "10"));
+	}
+	if (params.get("profile") !== null) {
+		options.use_polyomino_profile(params.get("profile"));
+	}
+	if (params.get("generator") === "maze") {
+		options.use_maze_generator(params.get("maze") ?? "backtracker", parseFloat(params.get("loops") ?? "0"));
+	} else if (params.get("generator") === "cave") {
+		options.use_cave_generator(
+			parseFloat(params.get("fill") ?? "0.45"),
+			parseInt(params.get("iterations") ?? "5"),
+			parseInt(params.get("birth") ?? "5"),
+			parseInt(params.get("survival") ?? "4"),
+		);
+	} else if (params.get("generator") === "dungeon") {
+		options.use_dungeon_generator(
+			params.get("rooms") ?? "bsp",
+			parseInt(params.get("min_room") ?? "4"),
+			parseInt(params.get("max_room") ?? "12"),
+			parseInt(params.get("corridor") ?? "1"),
+			parseInt(params.get("max_rooms") ?? "12"),
+		);
+	} else if (params.get("generator") === "wfc") {
+		options.use_wfc_example(params.get("example") ?? "ruins", parseInt(params.get("pattern") ?? "3"));
+	} else if (params.get("generator") !== null) {
+		options.use_generator(params.get("generator"));
+	}
+	return options;
+}
+
+function runWasmGame() {
+	let button = document.getElementById("flatland_restart");
+	clearInterval(gameInterval);
 	game.render();
 	// await new Promise(r => setTimeout(r, 1000));
-	var WasmGameInterval = setInterval(function() {
-		game.update_flatland();
+	gameInterval = setInterval(function() {
+		let events = JSON.parse(game.update_flatland());
 		game.render();
+		updateReplaySeek();
+		updateStepInfo();
 		if (game.is_game_over()) {
-			clearInterval(WasmGameInterval);
+			clearInterval(gameInterval);
+			console.log("Flatland final step events", events);
 			button.disabled = false;
 			link.disabled = false;
-			recorder.stop();
+			if (recorder.state !== "inactive") {
+				recorder.stop();
+			}
 		}
 	}, 100);
+}
+
+// Show why a game couldn't be started or loaded, or clear the message when given null.
+function showWasmError(error) {
+	document.getElementById("flatland_error").textContent = error ?? "";
+	if (error !== null) {
+		console.error("Flatland", error);
+		document.getElementById("flatland_restart").disabled = false;
+	}
+}
+
+function saveWasmGame() {
+	let blob = new Blob([game.save_json()], { type: "application/json" });
+	let save_link = document.createElement("a");
+	save_link.download = "flatland_save.json";
+	save_link.href = URL.createObjectURL(blob);
+	save_link.click();
+}
+
+async function loadWasmGame(input) {
+	if (input.files.length === 0) {
+		return;
+	}
+	let save_json = await input.files[0].text();
+	input.value = "";
+	try {
+		game = wasmBindings.WasmGame.load_json(save_json);
+	} catch (error) {
+		// Keep playing the current game.
+		showWasmError(error);
+		return;
+	}
+	showWasmError(null);
+	setReplayControls(false);
+	runWasmGame();
+}
+
+function saveWasmReplay() {
+	let blob = new Blob([game.save_replay()], { type: "application/json" });
+	let save_link = document.createElement("a");
+	save_link.download = "flatland_replay.json";
+	save_link.href = URL.createObjectURL(blob);
+	save_link.click();
+}
 
+async function loadWasmReplay(input) {
+	if (input.files.length === 0) {
+		return;
+	}
+	let replay_json = await input.files[0].text();
+	input.value = "";
+	try {
+		game = wasmBindings.WasmGame.load_replay(replay_json);
+	} catch (error) {
+		// Keep playing the current game.
+		showWasmError(error);
+		return;
+	}
+	showWasmError(null);
+	setReplayControls(true);
+	runWasmGame();
+}
+
+function setReplayControls(enabled) {
+	replaying = enabled;
+	let seek = document.getElementById("flatland_replay_seek");
+	seek.disabled = !enabled;
+	document.getElementById("flatland_replay_back").disabled = !enabled;
+	document.getElementById("flatland_replay_forward").disabled = !enabled;
+	for (let id of ["flatland_step_back", "flatland_step_forward", "flatland_rewind", "flatland_branch"]) {
+		document.getElementById(id).disabled = enabled;
+	}
+	if (enabled) {
+		seek.max = game.replay_length();
+		seek.value = 0;
+	}
 }
 
+function updateReplaySeek() {
+	if (replaying) {
+		document.getElementById("flatland_replay_seek").value = game.replay_step();
+	}
+}
+
+function stepWasmReplay(forward) {
+	clearInterval(gameInterval);
+	if (forward) {
+		game.replay_step_forward();
+	} else {
+		game.replay_step_back();
+	}
+	game.render();
+	updateReplaySeek();
+}
+
+function seekWasmReplay(input) {
+	clearInterval(gameInterval);
+	game.replay_seek(parseInt(input.value));
+	game.render();
+}
+
+function updateStepInfo() {
+	if (!replaying) {
+		document.getElementById("flatland_step").textContent =
+			"Step " + game.current_step() + " (history back to step " + game.earliest_step() + ")";
+	}
+}
+
+function showWasmHistoryStep(events) {
+	game.render();
+	updateStepInfo();
+	if (events !== undefined) {
+		console.log("Flatland step " + game.current_step() + " events", events);
+	}
+}
+
+function stepWasmHistory(forward) {
+	clearInterval(gameInterval);
+	if (forward) {
+		showWasmHistoryStep(JSON.parse(game.update_flatland()));
+	} else {
+		game.step_back();
+		showWasmHistoryStep();
+	}
+}
+
+function rewindWasmGame() {
+	clearInterval(gameInterval);
+	let step = parseInt(document.getElementById("flatland_rewind_step").value);
+	if (!game.rewind_to(step)) {
+		console.log("Flatland step " + step + " is not in the history");
+	}
+	showWasmHistoryStep();
+}
+
+function branchWasmGame() {
+	clearInterval(gameInterval);
+	let action = document.getElementById("flatland_hero_action").value;
+	showWasmHistoryStep(JSON.parse(game.branch_with_hero_action(action)));
+}
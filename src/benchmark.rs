@@ -0,0 +1,141 @@
+// Running the hero planner on MovingAI grid benchmarks
+
+use crate::game::{Game, GameRunningState};
+use crate::map::{Map, MovingAiError, MovingAiScenario};
+
+/// How the hero did on one benchmark scenario.
+pub struct BenchmarkResult {
+    pub scenario: MovingAiScenario,
+    pub running_state: GameRunningState,
+    pub steps_run: u32,
+    pub hero_path_length: u32,
+    /// Shortest 4-connected path from start to goal, the best the hero could possibly do.
+    pub grid_optimal_length: Option<u32>,
+}
+
+impl BenchmarkResult {
+    /// Hero path length divided by the published (octile) optimal length, or None if the hero
+    /// didn't reach the goal.
+    pub fn ratio_to_published_optimal(&self) -> Option<f64> {
+        if self.running_state != GameRunningState::HeroVictory {
+            return None;
+        }
+        return Some(self.hero_path_length as f64 / self.scenario.optimal_length);
+    }
+}
+
+/// Play one scenario on a copy of `terrain` with no enemies and no teleports, for at most
+/// `max_steps` steps.
+pub fn run_movingai_scenario(
+    terrain: &Map,
+    scenario: &MovingAiScenario,
+    max_steps: u32,
+    seed: Option<u64>,
+) -> Result<BenchmarkResult, MovingAiError> {
+    let mut game_map = terrain.clone();
+    game_map.place_movingai_scenario(scenario)?;
+    let grid = game_map.map.as_ref().expect("Scenario map should exist");
+    let grid_optimal_length = game_map.path_length(grid, scenario.start, scenario.goal);
+
+    let mut game =
+        Game::from_map(game_map, 0, seed).expect("Scenario always places one hero and one goal");
+    // Benchmark maps can be large, and nothing here steps back.
+    game.set_history_limit(0);
+    while !game.is_over() && game.game_state.num_steps_run < max_steps {
+        game.run_game_iteration();
+    }
+
+    return Ok(BenchmarkResult {
+        scenario: scenario.clone(),
+        running_state: game.game_state.running_state,
+        steps_run: game.game_state.num_steps_run,
+        hero_path_length: game.game_state.hero_path_length,
+        grid_optimal_length,
+    });
+}
+
+/// Native entry point: `flatland_wasm movingai <file.map> <file.scen> [max_steps]` runs every
+/// scenario and prints one tab-separated line per scenario.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_cli(args: &[String]) {
+    let [command, map_path, scen_path, rest @ ..] = args else {
+        eprintln!("usage: flatland_wasm movingai <file.map> <file.scen> [max_steps]");
+        return;
+    };
+    if command != "movingai" {
+        eprintln!("unknown command {command}");
+        return;
+    }
+    let max_steps: u32 = rest
+        .first()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(10_000);
+    let map_text = std::fs::read_to_string(map_path).expect("Map file should be readable");
+    let scen_text = std::fs::read_to_string(scen_path).expect("Scenario file should be readable");
+    let terrain = Map::from_movingai_map(&map_text).expect("Map file should parse");
+    let scenarios =
+        crate::map::parse_movingai_scenarios(&scen_text).expect("Scenario file should parse");
+
+    println!(
+        "bucket\tstart\tgoal\tresult\tsteps\thero_length\tgrid_optimal\tpublished_optimal\tratio"
+    );
+    let mut skipped = 0;
+    for scenario in &scenarios {
+        let result = match run_movingai_scenario(&terrain, scenario, max_steps, Some(0)) {
+            Ok(result) => result,
+            Err(error) => {
+                eprintln!(
+                    "skipping scenario {:?} -> {:?}: {:?}",
+                    scenario.start, scenario.goal, error
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+        let outcome = match result.running_state {
+            GameRunningState::HeroVictory => "reached",
+            GameRunningState::HeroFailure => "failed",
+            _ => "timeout",
+        };
+        println!(
+            "{}\t{:?}\t{:?}\t{}\t{}\t{}\t{}\t{:.4}\t{}",
+            scenario.bucket,
+            scenario.start,
+            scenario.goal,
+            outcome,
+            result.steps_run,
+            result.hero_path_length,
+            result
+                .grid_optimal_length
+                .map_or("-".to_string(), |length| length.to_string()),
+            scenario.optimal_length,
+            result
+                .ratio_to_published_optimal()
+                .map_or("-".to_string(), |ratio| format!("{ratio:.3}")),
+        );
+    }
+    if skipped > 0 {
+        eprintln!("skipped {skipped} of {} scenarios", scenarios.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::parse_movingai_scenarios;
+
+    #[test]
+    fn ratio_is_only_given_for_runs_that_reach_the_goal() {
+        let terrain =
+            Map::from_movingai_map("type octile\nheight 1\nwidth 4\nmap\n....\n").unwrap();
+        let scenarios =
+            parse_movingai_scenarios("0\tline.map\t4\t1\t0\t0\t3\t0\t3.00000000\n").unwrap();
+
+        let reached = run_movingai_scenario(&terrain, &scenarios[0], 100, Some(0)).unwrap();
+        assert_eq!(reached.running_state, GameRunningState::HeroVictory);
+        assert_eq!(reached.ratio_to_published_optimal(), Some(1.));
+
+        let timed_out = run_movingai_scenario(&terrain, &scenarios[0], 0, Some(0)).unwrap();
+        assert_eq!(timed_out.ratio_to_published_optimal(), None);
+    }
+}
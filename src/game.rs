@@ -1,19 +1,43 @@
 use crate::wasm_bindgen;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::{Rng, rng};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::active_entity::enemy::get_enemy_action;
 use crate::active_entity::hero::get_hero_action;
 use crate::map;
 use crate::map::MapGenerationError;
+use crate::map::MapGenerationOptions;
 use crate::map::MapItem;
+use crate::map::MapParseError;
+use crate::map::MapTopology;
+use history::History;
 
-pub struct Game<const DIMX: usize, const DIMY: usize> {
-    pub game_map: map::Map<DIMX, DIMY>,
+mod events;
+mod history;
+mod replay;
+mod rules;
+mod save;
+
+pub use events::{GameEvent, Hazard};
+pub use history::DEFAULT_HISTORY_LIMIT;
+pub use replay::{REPLAY_FORMAT_VERSION, Replay, ReplayPlayer};
+pub use rules::{ActionResolution, BoundaryPolicy, GameRules};
+pub use save::{SAVE_FORMAT_VERSION, SaveError};
+
+#[derive(Clone)]
+pub struct Game {
+    pub game_map: map::Map,
     pub game_state: GameState,
+    pub rules: GameRules,
+    /// Steps recorded since `start_recording`, if recording.
+    recording: Option<Replay>,
+    /// Past steps, for `step_back` and `rewind_to`.
+    history: History,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum GameRunningState {
     NotStarted,
     InProgress,
@@ -21,14 +45,18 @@ pub enum GameRunningState {
     HeroFailure,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct GameState {
     pub num_steps_run: u32,
     pub running_state: GameRunningState,
     pub hero_teleports_remaining: u32,
+    pub seed: u64,
+    /// Number of cells the hero has walked, not counting teleports.
+    #[serde(default)]
+    pub hero_path_length: u32,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum EntityAction {
     None,
     MoveUp,
@@ -39,27 +67,89 @@ pub enum EntityAction {
     HonorableSuicide,
 }
 
-#[derive(Debug)]
+impl EntityAction {
+    /// Look up an action by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<EntityAction> {
+        match name {
+            "none" => {
+                return Some(EntityAction::None);
+            }
+            "up" => {
+                return Some(EntityAction::MoveUp);
+            }
+            "down" => {
+                return Some(EntityAction::MoveDown);
+            }
+            "left" => {
+                return Some(EntityAction::MoveLeft);
+            }
+            "right" => {
+                return Some(EntityAction::MoveRight);
+            }
+            "teleport" => {
+                return Some(EntityAction::Teleport);
+            }
+            "suicide" => {
+                return Some(EntityAction::HonorableSuicide);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// An action requested by the entity at `entity_position`. The short serialized names keep
+/// replay files compact.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DisambiguatedEntityAction {
+    #[serde(rename = "at")]
     entity_position: [usize; 2],
+    #[serde(rename = "do")]
     entity_action: EntityAction,
+    /// Where a teleport lands, drawn when the action is requested so the step can be replayed
+    /// without the RNG.
+    #[serde(rename = "to", default, skip_serializing_if = "Option::is_none")]
+    teleport_destination: Option<[usize; 2]>,
+}
+
+/// Build the RNG for one stage of a seeded game.
+/// Stage 0 is map generation and stage n is simulation step n, so every stage can be
+/// reproduced from the seed alone without replaying the stages before it.
+pub fn seeded_rng(seed: u64, stage: u64) -> StdRng {
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&stage.to_le_bytes());
+    return StdRng::from_seed(key);
 }
 
-impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
+impl Game {
+    /// Create a new game on a `dimx` by `dimy` map. If no seed is given, one is picked at
+    /// random and stored in the game state so the run can still be reproduced later.
     pub fn new(
-        wall_coverage: f32,
-        num_enemies: usize,
+        dimx: usize,
+        dimy: usize,
+        map_options: &MapGenerationOptions,
         hero_quantity_teleports: u32,
-    ) -> Result<Game<DIMX, DIMY>, MapGenerationError> {
+        seed: Option<u64>,
+    ) -> Result<Game, MapGenerationError> {
+        let seed = seed.unwrap_or_else(rand::random);
         let mut new_game = Game {
-            game_map: map::Map::new(),
+            game_map: map::Map::new(dimx, dimy),
             game_state: GameState {
                 running_state: GameRunningState::NotStarted,
                 num_steps_run: 0,
                 hero_teleports_remaining: hero_quantity_teleports,
+                seed,
+                hero_path_length: 0,
             },
+            rules: GameRules::default(),
+            recording: None,
+            history: History::new(),
         };
-        let map_gen_attempt = new_game.game_map.generate_map(wall_coverage, num_enemies);
+        new_game.game_map.set_topology(map_options.topology);
+        let mut rng = seeded_rng(seed, 0);
+        let map_gen_attempt = new_game.game_map.generate_map(map_options, &mut rng);
         if map_gen_attempt.is_err() {
             return Err(map_gen_attempt.err().unwrap());
         }
@@ -67,38 +157,116 @@ impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
         return Ok(new_game);
     }
 
-    pub fn run_game_iteration(&mut self) {
-        let (new_map, new_state) = self.inner_run_game_iteration();
-        self.game_map = new_map;
-        self.game_state = new_state;
+    /// Start a game on an existing map, such as a level loaded with `Map::from_text`.
+    /// The seed only drives the simulation, since the map is already built.
+    pub fn from_map(
+        game_map: map::Map,
+        hero_quantity_teleports: u32,
+        seed: Option<u64>,
+    ) -> Result<Game, MapParseError> {
+        game_map.validate_level()?;
+        return Ok(Game {
+            game_map,
+            game_state: GameState {
+                running_state: GameRunningState::NotStarted,
+                num_steps_run: 0,
+                hero_teleports_remaining: hero_quantity_teleports,
+                seed: seed.unwrap_or_else(rand::random),
+                hero_path_length: 0,
+            },
+            rules: GameRules::default(),
+            recording: None,
+            history: History::new(),
+        });
+    }
+
+    /// Whether the hero has won or lost.
+    pub fn is_over(&self) -> bool {
+        return self.game_state.running_state == GameRunningState::HeroVictory
+            || self.game_state.running_state == GameRunningState::HeroFailure;
+    }
+
+    /// Change the rules. A recording in progress starts again from here, since a replay is
+    /// played back under the rules it started with.
+    pub fn set_rules(&mut self, rules: GameRules) {
+        self.rules = rules;
+        self.restart_recording();
+    }
+
+    /// Change how the map's edges behave, restarting any recording like `set_rules`.
+    pub fn set_topology(&mut self, topology: MapTopology) {
+        self.game_map.set_topology(topology);
+        self.restart_recording();
     }
 
-    fn inner_run_game_iteration(&self) -> (map::Map<DIMX, DIMY>, GameState) {
-        let requested_actions = self.get_action_requests_from_entities();
-        let (new_map, new_state) = self.apply_entity_actions(requested_actions);
-        return (new_map, new_state);
+    /// Advance the simulation by one step and return what happened in it.
+    pub fn run_game_iteration(&mut self) -> Vec<GameEvent> {
+        let (new_map, new_state, events, resolved_actions) = self.inner_run_game_iteration(None);
+        self.commit_step(new_map, new_state, resolved_actions);
+        return events;
     }
 
-    fn get_action_requests_from_entities(&self) -> Vec<DisambiguatedEntityAction> {
+    /// Work out the next step without changing the game. `hero_action`, if given, replaces
+    /// the action the hero would have chosen.
+    fn inner_run_game_iteration(
+        &self,
+        hero_action: Option<EntityAction>,
+    ) -> (
+        map::Map,
+        GameState,
+        Vec<GameEvent>,
+        Vec<DisambiguatedEntityAction>,
+    ) {
+        let mut rng = seeded_rng(
+            self.game_state.seed,
+            self.game_state.num_steps_run as u64 + 1,
+        );
+        let requested_actions = self.get_action_requests_from_entities(&mut rng, hero_action);
+        let (new_map, new_state, events) = self.resolve_actions(&requested_actions);
+        return (new_map, new_state, events, requested_actions);
+    }
+
+    /// Apply one step's actions, in order, according to the game's rules.
+    fn resolve_actions(
+        &self,
+        requested_actions: &[DisambiguatedEntityAction],
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
+        if self.rules.action_resolution == ActionResolution::Simultaneous {
+            return self.apply_simultaneous_actions(requested_actions);
+        }
+        return self.apply_entity_actions(requested_actions);
+    }
+
+    fn get_action_requests_from_entities(
+        &self,
+        rng: &mut StdRng,
+        hero_action: Option<EntityAction>,
+    ) -> Vec<DisambiguatedEntityAction> {
         let mut requested_actions: Vec<DisambiguatedEntityAction> = Vec::new();
-        if let Some(map) = self.game_map.map {
-            for rowidx in 0..DIMX {
-                for colidx in 0..DIMY {
+        if let Some(map) = &self.game_map.map {
+            for rowidx in 0..self.game_map.dimx() {
+                for colidx in 0..self.game_map.dimy() {
                     match map[rowidx][colidx] {
                         MapItem::EnemyEntity => {
                             requested_actions.push(DisambiguatedEntityAction {
                                 entity_position: [rowidx, colidx],
                                 entity_action: get_enemy_action([rowidx, colidx], &self.game_map),
+                                teleport_destination: None,
                             });
                         }
                         MapItem::HeroEntity => {
+                            // The hero still plans its move when overridden, so the RNG is
+                            // left where the rest of the step expects it.
+                            let planned_action = get_hero_action(
+                                [rowidx, colidx],
+                                &self.game_map,
+                                &self.game_state,
+                                rng,
+                            );
                             requested_actions.push(DisambiguatedEntityAction {
                                 entity_position: [rowidx, colidx],
-                                entity_action: get_hero_action(
-                                    [rowidx, colidx],
-                                    &self.game_map,
-                                    &self.game_state,
-                                ),
+                                entity_action: hero_action.unwrap_or(planned_action),
+                                teleport_destination: None,
                             });
                         }
                         _ => {
@@ -112,21 +280,51 @@ impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
             // If we do nothing, this function will just return an empty Vec
             // Honestly, that's fine for this project.
         }
-        requested_actions.shuffle(&mut rng()); // ouch spicy
+        match self.rules.action_resolution {
+            ActionResolution::SeededShuffle => {
+                requested_actions.shuffle(rng); // ouch spicy
+            }
+            ActionResolution::HeroFirst => {
+                requested_actions.sort_by_key(|action| !self.is_hero_at(action.entity_position));
+            }
+            ActionResolution::EnemiesFirst => {
+                requested_actions.sort_by_key(|action| self.is_hero_at(action.entity_position));
+            }
+            ActionResolution::Simultaneous => {}
+        }
+        for disambiguated_action in requested_actions.iter_mut() {
+            if disambiguated_action.entity_action == EntityAction::Teleport
+                && self.is_hero_at(disambiguated_action.entity_position)
+                && self.game_state.hero_teleports_remaining > 0
+            {
+                let unoccupied_positions = self
+                    .game_map
+                    .list_unoccupied_positions(self.game_map.map.as_ref().unwrap());
+                let position_idx = rng.random_range(0..unoccupied_positions.len());
+                disambiguated_action.teleport_destination =
+                    Some(unoccupied_positions[position_idx]);
+            }
+        }
         return requested_actions;
     }
 
+    fn is_hero_at(&self, [pos_x, pos_y]: [usize; 2]) -> bool {
+        return self.game_map.map.as_ref().unwrap()[pos_x][pos_y] == MapItem::HeroEntity;
+    }
+
     fn apply_entity_actions(
         &self,
-        requested_actions: Vec<DisambiguatedEntityAction>,
-    ) -> (map::Map<DIMX, DIMY>, GameState) {
+        requested_actions: &[DisambiguatedEntityAction],
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
         let mut next_map = self.game_map.clone();
         let mut working_map = next_map
             .map
+            .take()
             .expect("Game map must exist when applying entity actions!");
         let mut next_state = self.game_state;
         next_state.running_state = GameRunningState::InProgress;
         next_state.num_steps_run += 1;
+        let mut events: Vec<GameEvent> = Vec::new();
         for disambiguated_action in requested_actions {
             let action = disambiguated_action.entity_action;
             let [pos_x, pos_y] = disambiguated_action.entity_position;
@@ -136,81 +334,180 @@ impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
                 continue;
             }
 
-            let mut target_position: Option<[i32; 2]> = None;
-            match action {
-                EntityAction::None => {}
-                EntityAction::MoveUp => target_position = Some([pos_x as i32, pos_y as i32 + 1]),
-                EntityAction::MoveDown => target_position = Some([pos_x as i32, pos_y as i32 - 1]),
-                EntityAction::MoveLeft => target_position = Some([pos_x as i32 - 1, pos_y as i32]),
-                EntityAction::MoveRight => target_position = Some([pos_x as i32 + 1, pos_y as i32]),
-                EntityAction::Teleport => {
-                    if entity_type == MapItem::HeroEntity
-                        && self.game_state.hero_teleports_remaining > 0
-                    {
-                        let unoccupied_positions = self
-                            .game_map
-                            .list_unoccupied_positions(self.game_map.map.unwrap());
-                        let position_idx = rand::rng().random_range(0..unoccupied_positions.len());
-                        let [targ_x, targ_y] = unoccupied_positions.get(position_idx).unwrap();
-                        target_position = Some([*targ_x as i32, *targ_y as i32]);
-                        next_state.hero_teleports_remaining -= 1;
-                    }
+            let Some(requested_target) =
+                self.action_target(disambiguated_action, entity_type, &mut next_state)
+            else {
+                if action == EntityAction::Teleport {
+                    events.push(GameEvent::NoTeleportsLeft {
+                        entity: entity_type,
+                        at: [pos_x, pos_y],
+                    });
                 }
-                EntityAction::HonorableSuicide => {
-                    target_position = Some([pos_x as i32, pos_y as i32])
+                continue;
+            };
+            let Some([target_x, target_y]) = self.apply_boundary(requested_target) else {
+                events.push(GameEvent::Blocked {
+                    entity: entity_type,
+                    at: [pos_x, pos_y],
+                    toward: requested_target,
+                });
+                continue;
+            };
+            if target_x >= self.game_map.dimx() as i32
+                || target_y >= self.game_map.dimy() as i32
+                || target_x < 0
+                || target_y < 0
+            {
+                working_map[pos_x][pos_y] = MapItem::Junk;
+                if entity_type == MapItem::HeroEntity {
+                    // hero has died: game over
+                    next_state.running_state = GameRunningState::HeroFailure;
                 }
+                events.push(GameEvent::Destroyed {
+                    entity: entity_type,
+                    at: [pos_x, pos_y],
+                    hazard: Hazard::Edge,
+                });
+                continue;
             }
-            if let Some([target_x, target_y]) = target_position {
-                if target_x > DIMX as i32 || target_y > DIMY as i32 || target_x < 0 || target_y < 0
-                {
+            let [tx, ty] = [target_x as usize, target_y as usize];
+            match working_map[tx][ty] {
+                MapItem::Empty => {
+                    working_map[pos_x][pos_y] = MapItem::Empty;
+                    working_map[tx][ty] = entity_type;
+                    if action == EntityAction::Teleport {
+                        events.push(GameEvent::Teleported {
+                            entity: entity_type,
+                            from: [pos_x, pos_y],
+                            to: [tx, ty],
+                        });
+                    } else {
+                        if entity_type == MapItem::HeroEntity {
+                            next_state.hero_path_length += 1;
+                        }
+                        events.push(GameEvent::Moved {
+                            entity: entity_type,
+                            from: [pos_x, pos_y],
+                            to: [tx, ty],
+                        });
+                    }
+                }
+                MapItem::Obstacle => {
                     working_map[pos_x][pos_y] = MapItem::Junk;
                     if entity_type == MapItem::HeroEntity {
-                        // hero has died: game over
                         next_state.running_state = GameRunningState::HeroFailure;
                     }
-                    continue;
+                    events.push(GameEvent::Destroyed {
+                        entity: entity_type,
+                        at: [pos_x, pos_y],
+                        hazard: Hazard::Obstacle,
+                    });
                 }
-                let [tx, ty] = [target_x as usize, target_y as usize];
-                match working_map[tx][ty] {
-                    MapItem::Empty => {
-                        working_map[pos_x][pos_y] = MapItem::Empty;
-                        working_map[tx][ty] = entity_type;
-                    }
-                    MapItem::Obstacle => {
-                        working_map[pos_x][pos_y] = MapItem::Junk;
-                        if entity_type == MapItem::HeroEntity {
-                            next_state.running_state = GameRunningState::HeroFailure;
-                        }
-                    }
-                    MapItem::Junk => {
-                        working_map[pos_x][pos_y] = MapItem::Junk;
-                        if entity_type == MapItem::HeroEntity {
-                            next_state.running_state = GameRunningState::HeroFailure;
-                        }
+                MapItem::Junk => {
+                    working_map[pos_x][pos_y] = MapItem::Junk;
+                    if entity_type == MapItem::HeroEntity {
+                        next_state.running_state = GameRunningState::HeroFailure;
                     }
-                    MapItem::EnemyEntity => {
-                        working_map[pos_x][pos_y] = MapItem::Junk;
-                        working_map[tx][ty] = MapItem::Junk;
-                        if entity_type == MapItem::HeroEntity {
-                            next_state.running_state = GameRunningState::HeroFailure;
-                        }
+                    events.push(GameEvent::Destroyed {
+                        entity: entity_type,
+                        at: [pos_x, pos_y],
+                        hazard: Hazard::Junk,
+                    });
+                }
+                MapItem::EnemyEntity | MapItem::HeroEntity => {
+                    let other = working_map[tx][ty];
+                    working_map[pos_x][pos_y] = MapItem::Junk;
+                    working_map[tx][ty] = MapItem::Junk;
+                    if entity_type == MapItem::HeroEntity || other == MapItem::HeroEntity {
+                        next_state.running_state = GameRunningState::HeroFailure;
                     }
-                    MapItem::Goal => {
-                        if entity_type == MapItem::HeroEntity {
-                            next_state.running_state = GameRunningState::HeroVictory;
-                            working_map[pos_x][pos_y] = MapItem::Empty;
-                        }
+                    if [tx, ty] == [pos_x, pos_y] {
+                        events.push(GameEvent::Suicide {
+                            entity: entity_type,
+                            at: [pos_x, pos_y],
+                        });
+                    } else {
+                        events.push(GameEvent::Collided {
+                            entity: entity_type,
+                            at: [pos_x, pos_y],
+                            other,
+                            other_at: [tx, ty],
+                        });
                     }
-                    MapItem::HeroEntity => {
-                        working_map[pos_x][pos_y] = MapItem::Junk;
-                        working_map[tx][ty] = MapItem::Junk;
-                        next_state.running_state = GameRunningState::HeroFailure;
+                }
+                MapItem::Goal => {
+                    if entity_type == MapItem::HeroEntity {
+                        next_state.running_state = GameRunningState::HeroVictory;
+                        next_state.hero_path_length += 1;
+                        working_map[pos_x][pos_y] = MapItem::Empty;
+                        events.push(GameEvent::ReachedGoal {
+                            from: [pos_x, pos_y],
+                            goal: [tx, ty],
+                        });
+                    } else {
+                        events.push(GameEvent::Blocked {
+                            entity: entity_type,
+                            at: [pos_x, pos_y],
+                            toward: [target_x, target_y],
+                        });
                     }
                 }
             }
         }
         next_map.map = Some(working_map);
-        return (next_map, next_state);
+        return (next_map, next_state, events);
+    }
+
+    /// The cell the action sends its entity to, which may be off the map, or None if it stays
+    /// put. Teleports use up one of the hero's teleports in `next_state`.
+    fn action_target(
+        &self,
+        disambiguated_action: &DisambiguatedEntityAction,
+        entity_type: MapItem,
+        next_state: &mut GameState,
+    ) -> Option<[i32; 2]> {
+        let [pos_x, pos_y] = disambiguated_action.entity_position;
+        let mut target_position: Option<[i32; 2]> = None;
+        match disambiguated_action.entity_action {
+            EntityAction::None => {}
+            EntityAction::MoveUp => target_position = Some([pos_x as i32, pos_y as i32 + 1]),
+            EntityAction::MoveDown => target_position = Some([pos_x as i32, pos_y as i32 - 1]),
+            EntityAction::MoveLeft => target_position = Some([pos_x as i32 - 1, pos_y as i32]),
+            EntityAction::MoveRight => target_position = Some([pos_x as i32 + 1, pos_y as i32]),
+            EntityAction::Teleport => {
+                if entity_type == MapItem::HeroEntity
+                    && let Some([targ_x, targ_y]) = disambiguated_action.teleport_destination
+                {
+                    target_position = Some([targ_x as i32, targ_y as i32]);
+                    next_state.hero_teleports_remaining -= 1;
+                }
+            }
+            EntityAction::HonorableSuicide => target_position = Some([pos_x as i32, pos_y as i32]),
+        }
+        return target_position;
+    }
+
+    /// Apply the map's topology and the boundary policy to a target from `action_target`.
+    /// Returns None if a solid edge blocks the move; the target is only left off the map
+    /// under `BoundaryPolicy::Lethal`.
+    fn apply_boundary(&self, target_position: [i32; 2]) -> Option<[i32; 2]> {
+        let [target_x, target_y] = target_position;
+        let [dimx, dimy] = [self.game_map.dimx() as i32, self.game_map.dimy() as i32];
+        if target_x >= 0 && target_x < dimx && target_y >= 0 && target_y < dimy {
+            return Some(target_position);
+        }
+        if self.game_map.topology() == MapTopology::Torus {
+            // Moving off one edge of a torus comes back on the opposite one.
+            return Some([target_x.rem_euclid(dimx), target_y.rem_euclid(dimy)]);
+        }
+        match self.rules.boundary {
+            BoundaryPolicy::Solid => {
+                return None;
+            }
+            BoundaryPolicy::Lethal => {
+                return Some(target_position);
+            }
+        }
     }
 
     /// Print the game state to the terminal
@@ -222,7 +519,11 @@ impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
     fn generate_game_string(&self) -> String {
         let mut game_display_string: String = String::new();
         game_display_string.push('\u{250C}'); // light top-left corner
-        game_display_string.push_str(String::from('\u{2500}').repeat(DIMY * 2).as_str()); // light horizontal line
+        game_display_string.push_str(
+            String::from('\u{2500}')
+                .repeat(self.game_map.dimx() * 2)
+                .as_str(),
+        ); // light horizontal line
         game_display_string.push_str("\u{2510}\r\n"); // light top-right corner
         if let Some(map_string) = self.game_map.generate_display_string() {
             for line in map_string.lines() {
@@ -233,8 +534,448 @@ impl<const DIMX: usize, const DIMY: usize> Game<DIMX, DIMY> {
             }
         }
         game_display_string.push('\u{2514}'); // light bottom-left corner
-        game_display_string.push_str(String::from('\u{2500}').repeat(DIMY * 2).as_str()); // light horizontal lines
+        game_display_string.push_str(
+            String::from('\u{2500}')
+                .repeat(self.game_map.dimx() * 2)
+                .as_str(),
+        ); // light horizontal lines
         game_display_string.push_str("\u{2518}\r\n"); // light bottom-right corner
         return game_display_string;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply one action to the entity at `position` and return the resulting map and state.
+    fn step(
+        level: &str,
+        rules: GameRules,
+        position: [usize; 2],
+        action: EntityAction,
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
+        return step_on(map::Map::from_text(level).unwrap(), rules, position, action);
+    }
+
+    fn step_on(
+        game_map: map::Map,
+        rules: GameRules,
+        position: [usize; 2],
+        action: EntityAction,
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
+        let mut game = Game::from_map(game_map, 0, Some(0)).unwrap();
+        game.rules = rules;
+        let requested_actions = vec![DisambiguatedEntityAction {
+            entity_position: position,
+            entity_action: action,
+            teleport_destination: None,
+        }];
+        return game.resolve_actions(&requested_actions);
+    }
+
+    fn rules(boundary: BoundaryPolicy) -> GameRules {
+        return GameRules {
+            boundary,
+            ..GameRules::default()
+        };
+    }
+
+    fn torus(level: &str) -> map::Map {
+        let mut game_map = map::Map::from_text(level).unwrap();
+        game_map.set_topology(map::MapTopology::Torus);
+        return game_map;
+    }
+
+    fn item_at(game_map: &map::Map, [pos_x, pos_y]: [usize; 2]) -> MapItem {
+        return game_map.map.as_ref().unwrap()[pos_x][pos_y];
+    }
+
+    /// Run one step in which the hero takes `hero_action` and the enemies chase it, with the
+    /// actions ordered by `action_resolution`.
+    fn step_with_hero(
+        level: &str,
+        action_resolution: ActionResolution,
+        hero_action: EntityAction,
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
+        let mut game = Game::from_map(map::Map::from_text(level).unwrap(), 0, Some(0)).unwrap();
+        game.rules.action_resolution = action_resolution;
+        let mut rng = StdRng::seed_from_u64(0);
+        let requested_actions = game.get_action_requests_from_entities(&mut rng, Some(hero_action));
+        return game.resolve_actions(&requested_actions);
+    }
+
+    const LEVEL: &str = "..G\nE..\n..H";
+
+    #[test]
+    fn lethal_edge_kills_hero_moving_off_the_far_side() {
+        // Moving to x == dimx used to index past the end of the map.
+        let (game_map, state, _) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Lethal),
+            [2, 2],
+            EntityAction::MoveRight,
+        );
+        assert_eq!(item_at(&game_map, [2, 2]), MapItem::Junk);
+        assert_eq!(state.running_state, GameRunningState::HeroFailure);
+
+        let (game_map, state, _) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Lethal),
+            [2, 2],
+            EntityAction::MoveUp,
+        );
+        assert_eq!(item_at(&game_map, [2, 2]), MapItem::Junk);
+        assert_eq!(state.running_state, GameRunningState::HeroFailure);
+    }
+
+    #[test]
+    fn lethal_edge_turns_enemy_into_junk() {
+        let (game_map, state, _) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Lethal),
+            [0, 1],
+            EntityAction::MoveLeft,
+        );
+        assert_eq!(item_at(&game_map, [0, 1]), MapItem::Junk);
+        assert_eq!(state.running_state, GameRunningState::InProgress);
+    }
+
+    #[test]
+    fn solid_edge_blocks_hero_and_enemy() {
+        let (game_map, state, _) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Solid),
+            [2, 2],
+            EntityAction::MoveRight,
+        );
+        assert_eq!(item_at(&game_map, [2, 2]), MapItem::HeroEntity);
+        assert_eq!(state.running_state, GameRunningState::InProgress);
+        assert_eq!(state.hero_path_length, 0);
+
+        let (game_map, _, _) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Solid),
+            [0, 1],
+            EntityAction::MoveLeft,
+        );
+        assert_eq!(item_at(&game_map, [0, 1]), MapItem::EnemyEntity);
+    }
+
+    #[test]
+    fn edge_checks_use_each_axis_size() {
+        // Moving to exactly x == dimx or y == dimy used to index past the end of the map, so
+        // use a map that is wider than it is tall to catch the axes being mixed up.
+        const WIDE: &str = "G...\nH..E";
+        for (boundary, expected) in [
+            (BoundaryPolicy::Solid, MapItem::EnemyEntity),
+            (BoundaryPolicy::Lethal, MapItem::Junk),
+        ] {
+            for action in [EntityAction::MoveRight, EntityAction::MoveUp] {
+                let (game_map, _, _) = step(WIDE, rules(boundary), [3, 1], action);
+                assert_eq!(
+                    item_at(&game_map, [3, 1]),
+                    expected,
+                    "{boundary:?} {action:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn torus_moves_entities_to_the_opposite_side() {
+        for boundary in [BoundaryPolicy::Solid, BoundaryPolicy::Lethal] {
+            let (game_map, state, _) = step_on(
+                torus(LEVEL),
+                rules(boundary),
+                [2, 2],
+                EntityAction::MoveRight,
+            );
+            assert_eq!(item_at(&game_map, [2, 2]), MapItem::Empty);
+            assert_eq!(item_at(&game_map, [0, 2]), MapItem::HeroEntity);
+            assert_eq!(state.hero_path_length, 1);
+
+            let (game_map, _, _) = step_on(
+                torus(LEVEL),
+                rules(boundary),
+                [0, 1],
+                EntityAction::MoveLeft,
+            );
+            assert_eq!(item_at(&game_map, [0, 1]), MapItem::Empty);
+            assert_eq!(item_at(&game_map, [2, 1]), MapItem::EnemyEntity);
+        }
+    }
+
+    #[test]
+    fn torus_can_reach_the_goal_across_the_edge() {
+        let (_, state, _) = step_on(
+            torus(LEVEL),
+            rules(BoundaryPolicy::Lethal),
+            [2, 2],
+            EntityAction::MoveUp,
+        );
+        assert_eq!(state.running_state, GameRunningState::HeroVictory);
+    }
+
+    #[test]
+    fn boundaries_apply_to_simultaneous_moves() {
+        for (game_map, boundary, expected) in [
+            (
+                map::Map::from_text(LEVEL).unwrap(),
+                BoundaryPolicy::Solid,
+                MapItem::HeroEntity,
+            ),
+            (
+                map::Map::from_text(LEVEL).unwrap(),
+                BoundaryPolicy::Lethal,
+                MapItem::Junk,
+            ),
+            (torus(LEVEL), BoundaryPolicy::Lethal, MapItem::Empty),
+        ] {
+            let game_rules = GameRules {
+                action_resolution: ActionResolution::Simultaneous,
+                boundary,
+            };
+            let (game_map, _, _) = step_on(game_map, game_rules, [2, 2], EntityAction::MoveRight);
+            assert_eq!(item_at(&game_map, [2, 2]), expected, "{boundary:?}");
+        }
+    }
+
+    #[test]
+    fn edge_moves_are_reported_as_events() {
+        let (_, _, events) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Lethal),
+            [0, 1],
+            EntityAction::MoveLeft,
+        );
+        assert_eq!(
+            events,
+            vec![GameEvent::Destroyed {
+                entity: MapItem::EnemyEntity,
+                at: [0, 1],
+                hazard: Hazard::Edge,
+            }]
+        );
+
+        let (_, _, events) = step(
+            LEVEL,
+            rules(BoundaryPolicy::Solid),
+            [2, 2],
+            EntityAction::MoveRight,
+        );
+        assert_eq!(
+            events,
+            vec![GameEvent::Blocked {
+                entity: MapItem::HeroEntity,
+                at: [2, 2],
+                toward: [3, 2],
+            }]
+        );
+    }
+
+    #[test]
+    fn simultaneous_swap_is_one_collision() {
+        let game_map = map::Map::from_text("EE.\n...\nH.G").unwrap();
+        let mut game = Game::from_map(game_map, 0, Some(0)).unwrap();
+        game.rules.action_resolution = ActionResolution::Simultaneous;
+        let requested_actions = vec![
+            DisambiguatedEntityAction {
+                entity_position: [0, 0],
+                entity_action: EntityAction::MoveRight,
+                teleport_destination: None,
+            },
+            DisambiguatedEntityAction {
+                entity_position: [1, 0],
+                entity_action: EntityAction::MoveLeft,
+                teleport_destination: None,
+            },
+        ];
+        let (game_map, _, events) = game.apply_simultaneous_actions(&requested_actions);
+        assert_eq!(item_at(&game_map, [0, 0]), MapItem::Junk);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::Junk);
+        assert_eq!(
+            events,
+            vec![GameEvent::Collided {
+                entity: MapItem::EnemyEntity,
+                at: [0, 0],
+                other: MapItem::EnemyEntity,
+                other_at: [1, 0],
+            }]
+        );
+    }
+
+    #[test]
+    fn resolution_order_decides_whether_a_chased_hero_escapes() {
+        // The enemy steps onto the hero's cell as the hero steps away from it.
+        const CHASE: &str = "EH..\n...G";
+        let (game_map, state, _) =
+            step_with_hero(CHASE, ActionResolution::HeroFirst, EntityAction::MoveRight);
+        assert_eq!(state.running_state, GameRunningState::InProgress);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::EnemyEntity);
+        assert_eq!(item_at(&game_map, [2, 0]), MapItem::HeroEntity);
+
+        let (game_map, state, events) = step_with_hero(
+            CHASE,
+            ActionResolution::EnemiesFirst,
+            EntityAction::MoveRight,
+        );
+        assert_eq!(state.running_state, GameRunningState::HeroFailure);
+        assert_eq!(item_at(&game_map, [0, 0]), MapItem::Junk);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::Junk);
+        assert_eq!(
+            events,
+            vec![GameEvent::Collided {
+                entity: MapItem::EnemyEntity,
+                at: [0, 0],
+                other: MapItem::HeroEntity,
+                other_at: [1, 0],
+            }]
+        );
+    }
+
+    #[test]
+    fn simultaneous_moves_onto_one_cell_crash_there() {
+        // Both enemies head for the cell between them and the hero.
+        let (game_map, state, events) = step_with_hero(
+            ".E.\nE.H\n..G",
+            ActionResolution::Simultaneous,
+            EntityAction::None,
+        );
+        assert_eq!(state.running_state, GameRunningState::InProgress);
+        assert_eq!(item_at(&game_map, [1, 1]), MapItem::Junk);
+        assert_eq!(item_at(&game_map, [0, 1]), MapItem::Empty);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::Empty);
+        assert_eq!(item_at(&game_map, [2, 1]), MapItem::HeroEntity);
+        assert_eq!(events.len(), 1);
+
+        // The hero joining them makes three.
+        let (game_map, state, events) = step_with_hero(
+            ".E.\nE.H\n..G",
+            ActionResolution::Simultaneous,
+            EntityAction::MoveLeft,
+        );
+        assert_eq!(state.running_state, GameRunningState::HeroFailure);
+        assert_eq!(item_at(&game_map, [1, 1]), MapItem::Junk);
+        assert_eq!(item_at(&game_map, [2, 1]), MapItem::Empty);
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn simultaneous_chains_follow_into_vacated_cells() {
+        let (game_map, state, events) = step_with_hero(
+            "EEH.\n...G",
+            ActionResolution::Simultaneous,
+            EntityAction::MoveRight,
+        );
+        assert_eq!(state.running_state, GameRunningState::InProgress);
+        assert_eq!(item_at(&game_map, [0, 0]), MapItem::Empty);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::EnemyEntity);
+        assert_eq!(item_at(&game_map, [2, 0]), MapItem::EnemyEntity);
+        assert_eq!(item_at(&game_map, [3, 0]), MapItem::HeroEntity);
+        assert!(
+            events
+                .iter()
+                .all(|event| matches!(event, GameEvent::Moved { .. }))
+        );
+        assert_eq!(events.len(), 3);
+
+        // Applied one at a time in map order, the back enemy runs into the one in front.
+        let (game_map, _, _) = step_with_hero(
+            "EEH.\n...G",
+            ActionResolution::EnemiesFirst,
+            EntityAction::MoveRight,
+        );
+        assert_eq!(item_at(&game_map, [0, 0]), MapItem::Junk);
+        assert_eq!(item_at(&game_map, [1, 0]), MapItem::Junk);
+    }
+
+    #[test]
+    fn teleporting_without_teleports_left_is_reported() {
+        for action_resolution in [ActionResolution::HeroFirst, ActionResolution::Simultaneous] {
+            let (game_map, state, events) =
+                step_with_hero("H.G", action_resolution, EntityAction::Teleport);
+            assert_eq!(item_at(&game_map, [0, 0]), MapItem::HeroEntity);
+            assert_eq!(state.hero_teleports_remaining, 0);
+            assert_eq!(
+                events,
+                vec![GameEvent::NoTeleportsLeft {
+                    entity: MapItem::HeroEntity,
+                    at: [0, 0],
+                }],
+                "{action_resolution:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rewinding_restores_earlier_steps_and_branches_from_them() {
+        let game_map = map::Map::from_text("....G\n.....\nE....\n.....\nH....").unwrap();
+        let mut game = Game::from_map(game_map, 0, Some(7)).unwrap();
+        game.start_recording();
+        let mut snapshots = vec![game.game_map.generate_ascii_string().unwrap()];
+        for _ in 0..3 {
+            game.run_game_iteration();
+            snapshots.push(game.game_map.generate_ascii_string().unwrap());
+        }
+
+        assert!(game.step_back());
+        assert_eq!(game.game_state.num_steps_run, 2);
+        assert_eq!(game.game_map.generate_ascii_string().unwrap(), snapshots[2]);
+        assert!(game.rewind_to(1));
+        assert_eq!(game.game_map.generate_ascii_string().unwrap(), snapshots[1]);
+        assert_eq!(game.replay().unwrap().step_count(), 1);
+        assert!(!game.rewind_to(2));
+
+        // Stepping forward again reruns the same seeded step.
+        game.run_game_iteration();
+        assert_eq!(game.game_map.generate_ascii_string().unwrap(), snapshots[2]);
+
+        assert!(game.rewind_to(0));
+        assert!(!game.step_back());
+        game.run_game_iteration_with_hero_action(EntityAction::HonorableSuicide);
+        assert_eq!(game.game_state.running_state, GameRunningState::HeroFailure);
+        assert_eq!(game.replay().unwrap().step_count(), 1);
+    }
+
+    #[test]
+    fn history_limit_bounds_how_far_back_a_game_can_go() {
+        let game_map = map::Map::from_text("....G\n.....\nE....\n.....\nH....").unwrap();
+        let mut game = Game::from_map(game_map, 0, Some(7)).unwrap();
+        game.set_history_limit(2);
+        for _ in 0..3 {
+            game.run_game_iteration();
+        }
+        assert_eq!(game.earliest_step(), 1);
+        assert!(!game.rewind_to(0));
+        assert!(game.rewind_to(1));
+        assert!(!game.step_back());
+    }
+
+    #[test]
+    fn same_seed_gives_same_map_and_run() {
+        let options = MapGenerationOptions::default();
+        let mut first = Game::new(32, 32, &options, 5, Some(3)).unwrap();
+        let mut second = Game::new(32, 32, &options, 5, Some(3)).unwrap();
+        let other = Game::new(32, 32, &options, 5, Some(4)).unwrap();
+        assert_eq!(
+            first.game_map.generate_ascii_string(),
+            second.game_map.generate_ascii_string()
+        );
+        assert_ne!(
+            first.game_map.generate_ascii_string(),
+            other.game_map.generate_ascii_string()
+        );
+        while !first.is_over() && first.game_state.num_steps_run < 200 {
+            assert_eq!(first.run_game_iteration(), second.run_game_iteration());
+            assert_eq!(
+                first.game_map.generate_ascii_string(),
+                second.game_map.generate_ascii_string()
+            );
+        }
+        assert_eq!(
+            first.game_state.running_state,
+            second.game_state.running_state
+        );
+    }
+}
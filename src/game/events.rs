@@ -0,0 +1,61 @@
+// Typed record of what happened during a simulation step
+
+use serde::Serialize;
+
+use crate::map::MapItem;
+
+/// What turned an entity into junk, other than another entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Hazard {
+    Obstacle,
+    Junk,
+    /// The entity moved off a map edge under `BoundaryPolicy::Lethal`.
+    Edge,
+}
+
+/// One thing that happened to an entity during a step, as returned by
+/// `Game::run_game_iteration`. Positions are where things stood when the step began.
+/// Serializes with an `event` field naming the variant, for use from JS.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum GameEvent {
+    /// The entity walked onto an empty neighboring cell.
+    Moved {
+        entity: MapItem,
+        from: [usize; 2],
+        to: [usize; 2],
+    },
+    /// The entity tried to move but stayed put: a solid map edge was in the way, or an
+    /// enemy tried to step onto the goal. `toward` is the cell it aimed for, which may be off
+    /// the map.
+    Blocked {
+        entity: MapItem,
+        at: [usize; 2],
+        toward: [i32; 2],
+    },
+    /// Two entities ran into each other and both became junk.
+    Collided {
+        entity: MapItem,
+        at: [usize; 2],
+        other: MapItem,
+        other_at: [usize; 2],
+    },
+    /// The entity became junk on its own, by running into `hazard`.
+    Destroyed {
+        entity: MapItem,
+        at: [usize; 2],
+        hazard: Hazard,
+    },
+    /// The hero used a teleport.
+    Teleported {
+        entity: MapItem,
+        from: [usize; 2],
+        to: [usize; 2],
+    },
+    /// The entity tried to teleport with none of the hero's teleports left, and stayed put.
+    NoTeleportsLeft { entity: MapItem, at: [usize; 2] },
+    /// The hero stepped onto the goal and won.
+    ReachedGoal { from: [usize; 2], goal: [usize; 2] },
+    /// The entity gave up and turned itself into junk.
+    Suicide { entity: MapItem, at: [usize; 2] },
+}
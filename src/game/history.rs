@@ -0,0 +1,183 @@
+// Bounded history of past steps, for stepping back and branching
+
+use std::collections::VecDeque;
+
+use crate::game::{DisambiguatedEntityAction, EntityAction, Game, GameEvent, GameState};
+use crate::map::{self, MapItem};
+
+/// How many past steps a new game keeps.
+pub const DEFAULT_HISTORY_LIMIT: usize = 500;
+
+/// What one step changed: the cells it rewrote with what they held before, and the state
+/// before it. A step only moves a few entities, so this stays small on any map size.
+#[derive(Clone)]
+struct StepUndo {
+    changed_cells: Vec<([usize; 2], MapItem)>,
+    game_state: GameState,
+}
+
+/// How to undo each of the most recent steps, oldest first. Stepping forward again from a
+/// restored state reruns the same seeded step, so only the changes are kept.
+#[derive(Clone)]
+pub(super) struct History {
+    snapshots: VecDeque<StepUndo>,
+    limit: usize,
+}
+
+impl History {
+    pub(super) fn new() -> History {
+        return History {
+            snapshots: VecDeque::new(),
+            limit: DEFAULT_HISTORY_LIMIT,
+        };
+    }
+
+    fn push(&mut self, old_map: &map::Map, new_map: &map::Map, game_state: GameState) {
+        if self.limit == 0 {
+            return;
+        }
+        let (Some(old_grid), Some(new_grid)) = (&old_map.map, &new_map.map) else {
+            return;
+        };
+        let mut changed_cells: Vec<([usize; 2], MapItem)> = Vec::new();
+        for (pos_x, (old_column, new_column)) in old_grid.iter().zip(new_grid).enumerate() {
+            for (pos_y, (old_item, new_item)) in old_column.iter().zip(new_column).enumerate() {
+                if old_item != new_item {
+                    changed_cells.push(([pos_x, pos_y], *old_item));
+                }
+            }
+        }
+        self.snapshots.push_back(StepUndo {
+            changed_cells,
+            game_state,
+        });
+        while self.snapshots.len() > self.limit {
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+impl Game {
+    /// Keep at most `limit` past steps, forgetting the oldest ones beyond it. Zero turns the
+    /// history off.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
+        while self.history.snapshots.len() > limit {
+            self.history.snapshots.pop_front();
+        }
+    }
+
+    pub fn history_limit(&self) -> usize {
+        return self.history.limit;
+    }
+
+    /// The earliest step `rewind_to` can go back to.
+    pub fn earliest_step(&self) -> u32 {
+        if let Some(undo) = self.history.snapshots.front() {
+            return undo.game_state.num_steps_run;
+        }
+        return self.game_state.num_steps_run;
+    }
+
+    /// Undo the last step. Returns false if there is no history to go back to.
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.snapshots.pop_back() else {
+            return false;
+        };
+        if let Some(grid) = self.game_map.map.as_mut() {
+            for ([pos_x, pos_y], item) in undo.changed_cells {
+                grid[pos_x][pos_y] = item;
+            }
+        }
+        self.game_state = undo.game_state;
+        if let Some(recording) = self.recording.as_mut()
+            && recording.steps.pop().is_none()
+        {
+            // Recording started after this step, so start it again from here.
+            self.start_recording();
+        }
+        return true;
+    }
+
+    /// Go back to the game as it was after `step` steps. Returns false, leaving the game as
+    /// it is, if `step` is in the future or older than the history reaches.
+    pub fn rewind_to(&mut self, step: u32) -> bool {
+        if step < self.earliest_step() || step > self.game_state.num_steps_run {
+            return false;
+        }
+        while self.game_state.num_steps_run > step {
+            self.step_back();
+        }
+        return true;
+    }
+
+    /// Advance one step like `run_game_iteration`, but with the hero doing `hero_action`
+    /// instead of what it would have chosen. After `step_back` or `rewind_to`, this starts a
+    /// new branch from the earlier point.
+    pub fn run_game_iteration_with_hero_action(
+        &mut self,
+        hero_action: EntityAction,
+    ) -> Vec<GameEvent> {
+        let (new_map, new_state, events, resolved_actions) =
+            self.inner_run_game_iteration(Some(hero_action));
+        self.commit_step(new_map, new_state, resolved_actions);
+        return events;
+    }
+
+    /// Make the result of a step the current game, remembering the game before it.
+    pub(super) fn commit_step(
+        &mut self,
+        new_map: map::Map,
+        new_state: GameState,
+        resolved_actions: Vec<DisambiguatedEntityAction>,
+    ) {
+        let old_map = std::mem::replace(&mut self.game_map, new_map);
+        let old_state = std::mem::replace(&mut self.game_state, new_state);
+        self.history.push(&old_map, &self.game_map, old_state);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.steps.push(resolved_actions);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapGenerationOptions;
+
+    #[test]
+    fn history_evicts_the_oldest_steps_and_keeps_only_changes() {
+        let options = MapGenerationOptions {
+            wall_coverage: 0.1,
+            coverage_tolerance: 0.01,
+            ..Default::default()
+        };
+        let mut game = Game::new(128, 128, &options, 0, Some(2)).unwrap();
+        game.set_history_limit(5);
+        let mut snapshots = vec![game.game_map.generate_ascii_string().unwrap()];
+        while !game.is_over() && snapshots.len() <= 20 {
+            game.run_game_iteration();
+            snapshots.push(game.game_map.generate_ascii_string().unwrap());
+            let steps_run = game.game_state.num_steps_run;
+            assert_eq!(game.earliest_step(), steps_run.saturating_sub(5));
+            // A step touches a handful of cells, not the whole 128x128 map.
+            assert!(
+                game.history
+                    .snapshots
+                    .iter()
+                    .all(|undo| undo.changed_cells.len() <= 2 * (options.num_enemies + 2))
+            );
+        }
+        let steps_run = game.game_state.num_steps_run;
+        assert!(steps_run > 5);
+
+        let earliest = game.earliest_step();
+        assert!(!game.rewind_to(earliest - 1));
+        assert!(game.rewind_to(earliest));
+        assert_eq!(
+            game.game_map.generate_ascii_string().unwrap(),
+            snapshots[earliest as usize]
+        );
+        assert!(!game.step_back());
+    }
+}
@@ -0,0 +1,218 @@
+// Replay recording and playback
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::save::SavedGame;
+use crate::game::{DisambiguatedEntityAction, Game, GameEvent, SaveError};
+
+/// Bumped whenever the layout of `Replay` changes incompatibly.
+pub const REPLAY_FORMAT_VERSION: u32 = 1;
+const REPLAY_FORMAT_NAME: &str = "flatland-replay";
+
+/// How many steps apart `ReplayPlayer` keeps snapshots, which bounds the work of seeking
+/// backwards.
+const CHECKPOINT_INTERVAL: usize = 64;
+
+/// A recorded run: the game as it was when recording started, and the actions resolved in
+/// every step since, in the order they were applied. Playback doesn't run the hero or enemy
+/// logic or the RNG, so a replay stays valid even if those change.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    format: String,
+    version: u32,
+    initial: SavedGame,
+    pub(super) steps: Vec<Vec<DisambiguatedEntityAction>>,
+}
+
+impl Replay {
+    /// Number of recorded steps.
+    pub fn step_count(&self) -> usize {
+        return self.steps.len();
+    }
+
+    /// Serialize the replay as compact JSON.
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string(self).expect("Replays always serialize");
+    }
+
+    /// Read a replay written by `to_json`.
+    pub fn from_json(text: &str) -> Result<Replay, SaveError> {
+        let replay: Replay = serde_json::from_str(text)
+            .map_err(|error| SaveError::InvalidJson(error.to_string()))?;
+        if replay.format != REPLAY_FORMAT_NAME || replay.version != REPLAY_FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion {
+                format: replay.format,
+                version: replay.version,
+            });
+        }
+        return Ok(replay);
+    }
+
+    /// Write the replay to a JSON file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_file(&self, path: &std::path::Path) -> Result<(), SaveError> {
+        return std::fs::write(path, self.to_json())
+            .map_err(|error| SaveError::Io(error.to_string()));
+    }
+
+    /// Read a replay back from a JSON file written by `save_to_file`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_file(path: &std::path::Path) -> Result<Replay, SaveError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|error| SaveError::Io(error.to_string()))?;
+        return Replay::from_json(&text);
+    }
+}
+
+impl Game {
+    /// Start recording a replay from the game as it is now, dropping any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Replay {
+            format: REPLAY_FORMAT_NAME.to_string(),
+            version: REPLAY_FORMAT_VERSION,
+            initial: SavedGame::from_game(self),
+            steps: Vec::new(),
+        });
+    }
+
+    /// Start the recording in progress, if any, again from the game as it is now.
+    pub(super) fn restart_recording(&mut self) {
+        if self.recording.is_some() {
+            self.start_recording();
+        }
+    }
+
+    /// The replay recorded so far, if recording.
+    pub fn replay(&self) -> Option<&Replay> {
+        return self.recording.as_ref();
+    }
+
+    /// Stop recording and return the replay.
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        return self.recording.take();
+    }
+}
+
+/// Rebuilds the game at any step of a replay.
+pub struct ReplayPlayer {
+    replay: Replay,
+    /// `checkpoints[i]` is the game after `i * CHECKPOINT_INTERVAL` steps, added as playback
+    /// first reaches it.
+    checkpoints: Vec<Game>,
+    game: Game,
+    step: usize,
+}
+
+impl ReplayPlayer {
+    /// Load a replay, positioned before its first step.
+    pub fn new(replay: Replay) -> Result<ReplayPlayer, SaveError> {
+        let game = replay.initial.clone().into_game()?;
+        return Ok(ReplayPlayer {
+            replay,
+            checkpoints: vec![game.clone()],
+            game,
+            step: 0,
+        });
+    }
+
+    pub fn replay(&self) -> &Replay {
+        return &self.replay;
+    }
+
+    /// The game as it was after `current_step` steps of the replay.
+    pub fn game(&self) -> &Game {
+        return &self.game;
+    }
+
+    pub fn current_step(&self) -> usize {
+        return self.step;
+    }
+
+    pub fn step_count(&self) -> usize {
+        return self.replay.step_count();
+    }
+
+    pub fn at_end(&self) -> bool {
+        return self.step == self.step_count();
+    }
+
+    /// Play the next recorded step and return its events, or None at the end of the replay.
+    pub fn step_forward(&mut self) -> Option<Vec<GameEvent>> {
+        let actions = self.replay.steps.get(self.step)?;
+        let (next_map, next_state, events) = self.game.resolve_actions(actions);
+        self.game.game_map = next_map;
+        self.game.game_state = next_state;
+        self.step += 1;
+        if self.step == self.checkpoints.len() * CHECKPOINT_INTERVAL {
+            self.checkpoints.push(self.game.clone());
+        }
+        return Some(events);
+    }
+
+    /// Go back one step. Returns false if already at the start.
+    pub fn step_back(&mut self) -> bool {
+        if self.step == 0 {
+            return false;
+        }
+        self.seek(self.step - 1);
+        return true;
+    }
+
+    /// Jump to the game as it was after `step` steps, or to the end if the replay is shorter.
+    pub fn seek(&mut self, step: usize) {
+        let step = step.min(self.step_count());
+        let checkpoint = (step / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        if step < self.step || checkpoint * CHECKPOINT_INTERVAL > self.step {
+            self.game = self.checkpoints[checkpoint].clone();
+            self.step = checkpoint * CHECKPOINT_INTERVAL;
+        }
+        while self.step < step {
+            self.step_forward();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ActionResolution, BoundaryPolicy, GameRules};
+    use crate::map::{MapGenerationOptions, MapTopology};
+
+    #[test]
+    fn replay_follows_settings_changed_after_recording_started() {
+        let options = MapGenerationOptions {
+            num_enemies: 10,
+            ..Default::default()
+        };
+        let mut game = Game::new(24, 24, &options, 3, Some(11)).unwrap();
+        // The web page records from the moment the game is created, then applies its settings.
+        game.start_recording();
+        game.set_rules(GameRules {
+            action_resolution: ActionResolution::Simultaneous,
+            boundary: BoundaryPolicy::Solid,
+        });
+        game.set_topology(MapTopology::Torus);
+
+        let mut snapshots = vec![game.game_map.generate_ascii_string().unwrap()];
+        while !game.is_over() && snapshots.len() <= 60 {
+            game.run_game_iteration();
+            snapshots.push(game.game_map.generate_ascii_string().unwrap());
+        }
+
+        let replay = Replay::from_json(&game.replay().unwrap().to_json()).unwrap();
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        assert_eq!(player.step_count(), snapshots.len() - 1);
+        assert_eq!(player.game().rules, game.rules);
+        assert_eq!(player.game().game_map.topology(), MapTopology::Torus);
+        for (step, snapshot) in snapshots.iter().enumerate() {
+            if step > 0 {
+                player.step_forward().unwrap();
+            }
+            assert_eq!(
+                &player.game().game_map.generate_ascii_string().unwrap(),
+                snapshot,
+                "step {step}"
+            );
+        }
+    }
+}
@@ -0,0 +1,335 @@
+// Simulation rules and action resolution
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::game::{
+    DisambiguatedEntityAction, EntityAction, Game, GameEvent, GameRunningState, GameState, Hazard,
+};
+use crate::map;
+use crate::map::MapItem;
+
+/// How the actions requested in one step are applied to the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ActionResolution {
+    /// Apply actions one at a time in an order shuffled with the step's seeded RNG, so each
+    /// move sees the moves made before it.
+    #[default]
+    SeededShuffle,
+    /// Apply the hero's action first, then the enemies' in map order.
+    HeroFirst,
+    /// Apply the enemies' actions in map order, then the hero's.
+    EnemiesFirst,
+    /// Resolve every action against the map as it was at the start of the step. Entities
+    /// that swap places, move onto the same cell, or move onto an entity that stays put
+    /// collide and become junk; entities following each other all move.
+    Simultaneous,
+}
+
+impl ActionResolution {
+    /// Look up a resolution policy by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<ActionResolution> {
+        match name {
+            "shuffle" => {
+                return Some(ActionResolution::SeededShuffle);
+            }
+            "hero_first" => {
+                return Some(ActionResolution::HeroFirst);
+            }
+            "enemies_first" => {
+                return Some(ActionResolution::EnemiesFirst);
+            }
+            "simultaneous" => {
+                return Some(ActionResolution::Simultaneous);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// What happens to an entity that moves off the edge of a bounded map. For edges that wrap
+/// around, use `MapTopology::Torus`, which the hero and enemies also plan around.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum BoundaryPolicy {
+    /// The edge blocks the move and the entity stays where it is.
+    Solid,
+    /// The entity falls off and becomes junk where it stood.
+    #[default]
+    Lethal,
+}
+
+impl BoundaryPolicy {
+    /// Look up a boundary policy by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<BoundaryPolicy> {
+        match name {
+            "solid" => {
+                return Some(BoundaryPolicy::Solid);
+            }
+            "lethal" => {
+                return Some(BoundaryPolicy::Lethal);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// Rules the simulation follows, separate from the map and the per-game state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GameRules {
+    pub action_resolution: ActionResolution,
+    /// Ignored on a torus map, which has no edges to cross.
+    #[serde(default)]
+    pub boundary: BoundaryPolicy,
+}
+
+/// What happens to one entity in a simultaneous step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Stays,
+    /// Tried to move to the cell, but a solid edge or the goal stopped it.
+    Blocked([i32; 2]),
+    Moves([usize; 2]),
+    /// Moved onto the same cell as other entities, which becomes junk.
+    CrashesAt([usize; 2]),
+    /// Ran into the entity with this index and became junk where it stood.
+    Collides(usize),
+    /// Became junk where it stood.
+    Destroyed(Hazard),
+    Suicide,
+    ReachesGoal([usize; 2]),
+}
+
+impl Outcome {
+    /// Whether the entity's starting cell is free for someone else by the end of the step.
+    fn leaves(&self) -> bool {
+        return matches!(
+            self,
+            Outcome::Moves(_) | Outcome::CrashesAt(_) | Outcome::ReachesGoal(_)
+        );
+    }
+}
+
+impl Game {
+    /// Apply every action at once (see `ActionResolution::Simultaneous`).
+    pub(super) fn apply_simultaneous_actions(
+        &self,
+        requested_actions: &[DisambiguatedEntityAction],
+    ) -> (map::Map, GameState, Vec<GameEvent>) {
+        let mut next_map = self.game_map.clone();
+        let mut working_map = next_map
+            .map
+            .take()
+            .expect("Game map must exist when applying entity actions!");
+        let mut next_state = self.game_state;
+        next_state.running_state = GameRunningState::InProgress;
+        next_state.num_steps_run += 1;
+
+        // Where each entity tries to go, judged against the map at the start of the step.
+        let mut entities: Vec<([usize; 2], MapItem, bool)> = Vec::new();
+        let mut outcomes: Vec<Outcome> = Vec::new();
+        for disambiguated_action in requested_actions {
+            let origin = disambiguated_action.entity_position;
+            let entity_type = working_map[origin[0]][origin[1]];
+            let teleports = disambiguated_action.entity_action == EntityAction::Teleport;
+            let requested_target =
+                self.action_target(disambiguated_action, entity_type, &mut next_state);
+            let target = requested_target.map(|target| (target, self.apply_boundary(target)));
+            let outcome = match target {
+                None => Outcome::Stays,
+                Some((requested, None)) => Outcome::Blocked(requested),
+                Some((_, Some([target_x, target_y])))
+                    if target_x < 0
+                        || target_y < 0
+                        || target_x >= self.game_map.dimx() as i32
+                        || target_y >= self.game_map.dimy() as i32 =>
+                {
+                    Outcome::Destroyed(Hazard::Edge)
+                }
+                Some((_, Some([target_x, target_y]))) => {
+                    let target = [target_x as usize, target_y as usize];
+                    match working_map[target[0]][target[1]] {
+                        _ if target == origin => Outcome::Suicide,
+                        MapItem::Obstacle => Outcome::Destroyed(Hazard::Obstacle),
+                        MapItem::Junk => Outcome::Destroyed(Hazard::Junk),
+                        MapItem::Goal if entity_type == MapItem::HeroEntity => {
+                            Outcome::ReachesGoal(target)
+                        }
+                        MapItem::Goal => Outcome::Blocked([target_x, target_y]),
+                        _ => Outcome::Moves(target),
+                    }
+                }
+            };
+            entities.push((origin, entity_type, teleports));
+            outcomes.push(outcome);
+        }
+
+        // Two or more entities moving onto the same cell crash there.
+        let mut movers_per_target: HashMap<[usize; 2], usize> = HashMap::new();
+        for outcome in &outcomes {
+            if let Outcome::Moves(target) = outcome {
+                *movers_per_target.entry(*target).or_insert(0) += 1;
+            }
+        }
+        for outcome in outcomes.iter_mut() {
+            if let Outcome::Moves(target) = *outcome
+                && movers_per_target[&target] > 1
+            {
+                *outcome = Outcome::CrashesAt(target);
+            }
+        }
+
+        // A move onto an occupied cell only succeeds if the occupant gets out of the way.
+        // Blocking spreads back along chains of followers, so repeat until nothing changes.
+        let occupants: HashMap<[usize; 2], usize> = entities
+            .iter()
+            .enumerate()
+            .map(|(index, (origin, _, _))| (*origin, index))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..outcomes.len() {
+                let target = match outcomes[index] {
+                    Outcome::Moves(target) | Outcome::CrashesAt(target) => target,
+                    _ => {
+                        continue;
+                    }
+                };
+                let Some(&occupant) = occupants.get(&target) else {
+                    continue;
+                };
+                let swapping = outcomes[occupant] == Outcome::Moves(entities[index].0);
+                if outcomes[occupant].leaves() && !swapping {
+                    continue;
+                }
+                let occupant_was_there = matches!(
+                    outcomes[occupant],
+                    Outcome::Stays | Outcome::Blocked(_) | Outcome::Moves(_)
+                );
+                if occupant_was_there {
+                    // Swapping places or hitting an entity that stays: both become junk.
+                    outcomes[occupant] = Outcome::Collides(index);
+                    if let Outcome::Moves(_) = outcomes[index] {
+                        outcomes[index] = Outcome::Collides(occupant);
+                    }
+                    changed = true;
+                } else if let Outcome::Moves(_) = outcomes[index] {
+                    // The occupant already turned into junk where it stood.
+                    outcomes[index] = Outcome::Destroyed(Hazard::Junk);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut events: Vec<GameEvent> = Vec::new();
+        for (index, (origin, _, _)) in entities.iter().enumerate() {
+            if outcomes[index].leaves() {
+                working_map[origin[0]][origin[1]] = MapItem::Empty;
+            } else if !matches!(outcomes[index], Outcome::Stays | Outcome::Blocked(_)) {
+                working_map[origin[0]][origin[1]] = MapItem::Junk;
+            }
+        }
+        for (index, &(origin, entity_type, teleports)) in entities.iter().enumerate() {
+            let is_hero = entity_type == MapItem::HeroEntity;
+            match outcomes[index] {
+                Outcome::Stays => {
+                    if teleports {
+                        events.push(GameEvent::NoTeleportsLeft {
+                            entity: entity_type,
+                            at: origin,
+                        });
+                    }
+                }
+                Outcome::Blocked(toward) => {
+                    events.push(GameEvent::Blocked {
+                        entity: entity_type,
+                        at: origin,
+                        toward,
+                    });
+                }
+                Outcome::Moves(target) => {
+                    working_map[target[0]][target[1]] = entity_type;
+                    if teleports {
+                        events.push(GameEvent::Teleported {
+                            entity: entity_type,
+                            from: origin,
+                            to: target,
+                        });
+                    } else {
+                        if is_hero {
+                            next_state.hero_path_length += 1;
+                        }
+                        events.push(GameEvent::Moved {
+                            entity: entity_type,
+                            from: origin,
+                            to: target,
+                        });
+                    }
+                }
+                Outcome::CrashesAt(target) => {
+                    working_map[target[0]][target[1]] = MapItem::Junk;
+                    if is_hero {
+                        next_state.running_state = GameRunningState::HeroFailure;
+                    }
+                    // Pair every crasher with the first one headed for the same cell.
+                    let first = outcomes
+                        .iter()
+                        .position(|outcome| *outcome == Outcome::CrashesAt(target))
+                        .unwrap();
+                    if first != index {
+                        events.push(GameEvent::Collided {
+                            entity: entity_type,
+                            at: origin,
+                            other: entities[first].1,
+                            other_at: entities[first].0,
+                        });
+                    }
+                }
+                Outcome::Collides(other) => {
+                    if is_hero {
+                        next_state.running_state = GameRunningState::HeroFailure;
+                    }
+                    // Report each pair that ran into each other once.
+                    if outcomes[other] != Outcome::Collides(index) || index < other {
+                        events.push(GameEvent::Collided {
+                            entity: entity_type,
+                            at: origin,
+                            other: entities[other].1,
+                            other_at: entities[other].0,
+                        });
+                    }
+                }
+                Outcome::Destroyed(hazard) => {
+                    if is_hero {
+                        next_state.running_state = GameRunningState::HeroFailure;
+                    }
+                    events.push(GameEvent::Destroyed {
+                        entity: entity_type,
+                        at: origin,
+                        hazard,
+                    });
+                }
+                Outcome::Suicide => {
+                    if is_hero {
+                        next_state.running_state = GameRunningState::HeroFailure;
+                    }
+                    events.push(GameEvent::Suicide {
+                        entity: entity_type,
+                        at: origin,
+                    });
+                }
+                Outcome::ReachesGoal(goal) => {
+                    next_state.running_state = GameRunningState::HeroVictory;
+                    next_state.hero_path_length += 1;
+                    events.push(GameEvent::ReachedGoal { from: origin, goal });
+                }
+            }
+        }
+        next_map.map = Some(working_map);
+        return (next_map, next_state, events);
+    }
+}
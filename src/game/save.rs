@@ -0,0 +1,197 @@
+// Versioned JSON save files for whole games
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::history::History;
+use crate::game::{Game, GameRules, GameState};
+use crate::map::{Map, MapParseError, MapTopology};
+
+/// Bumped whenever the layout of `SavedGame` changes incompatibly.
+pub const SAVE_FORMAT_VERSION: u32 = 1;
+const SAVE_FORMAT_NAME: &str = "flatland-game";
+
+/// Why a saved game or replay couldn't be read or written.
+#[derive(Debug)]
+pub enum SaveError {
+    /// The text isn't JSON, or doesn't have the fields of a saved game.
+    InvalidJson(String),
+    /// The file isn't a Flatland save, or was written by a newer version.
+    UnsupportedVersion { format: String, version: u32 },
+    /// The saved map rows didn't parse, or didn't match the saved dimensions.
+    InvalidMap(Option<MapParseError>),
+    /// Reading or writing the save file failed.
+    Io(String),
+}
+
+/// On-disk layout of a saved game. The map is stored as rows in the ASCII text format so
+/// saves stay readable when attached to a bug report.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct SavedGame {
+    format: String,
+    version: u32,
+    width: usize,
+    height: usize,
+    map: Vec<String>,
+    #[serde(default)]
+    topology: MapTopology,
+    #[serde(default)]
+    rules: GameRules,
+    state: GameState,
+}
+
+impl SavedGame {
+    pub(super) fn from_game(game: &Game) -> SavedGame {
+        let map_text = game
+            .game_map
+            .generate_ascii_string()
+            .expect("Game map must exist when saving!");
+        return SavedGame {
+            format: SAVE_FORMAT_NAME.to_string(),
+            version: SAVE_FORMAT_VERSION,
+            width: game.game_map.dimx(),
+            height: game.game_map.dimy(),
+            map: map_text.lines().map(|line| line.to_string()).collect(),
+            topology: game.game_map.topology(),
+            rules: game.rules,
+            state: game.game_state,
+        };
+    }
+
+    pub(super) fn into_game(self) -> Result<Game, SaveError> {
+        if self.format != SAVE_FORMAT_NAME || self.version != SAVE_FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion {
+                format: self.format,
+                version: self.version,
+            });
+        }
+        let mut game_map = Map::from_text(&self.map.join("\n"))
+            .map_err(|error| SaveError::InvalidMap(Some(error)))?;
+        if game_map.dimx() != self.width || game_map.dimy() != self.height {
+            return Err(SaveError::InvalidMap(None));
+        }
+        game_map.set_topology(self.topology);
+        return Ok(Game {
+            game_map,
+            game_state: self.state,
+            rules: self.rules,
+            recording: None,
+            history: History::new(),
+        });
+    }
+}
+
+impl Game {
+    /// Serialize the whole game (map, topology, rules, running state, step count, teleports
+    /// and seed).
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(&SavedGame::from_game(self))
+            .expect("Saved games always serialize");
+    }
+
+    /// Restore a game written by `to_json`. The restored game continues exactly as the saved
+    /// one would have, since every step's randomness comes from the saved seed.
+    pub fn from_json(text: &str) -> Result<Game, SaveError> {
+        let saved_game: SavedGame = serde_json::from_str(text)
+            .map_err(|error| SaveError::InvalidJson(error.to_string()))?;
+        return saved_game.into_game();
+    }
+
+    /// Write the game to a JSON file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_file(&self, path: &std::path::Path) -> Result<(), SaveError> {
+        return std::fs::write(path, self.to_json())
+            .map_err(|error| SaveError::Io(error.to_string()));
+    }
+
+    /// Read a game back from a JSON file written by `save_to_file`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_file(path: &std::path::Path) -> Result<Game, SaveError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|error| SaveError::Io(error.to_string()))?;
+        return Game::from_json(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ActionResolution, BoundaryPolicy, EntityAction, GameRunningState};
+    use crate::map::MapGenerationOptions;
+
+    fn assert_same_game(first: &Game, second: &Game) {
+        assert_eq!(
+            first.game_map.generate_ascii_string(),
+            second.game_map.generate_ascii_string()
+        );
+        assert_eq!(first.game_map.topology(), second.game_map.topology());
+        assert_eq!(first.rules, second.rules);
+        assert_eq!(
+            serde_json::to_value(first.game_state).unwrap(),
+            serde_json::to_value(second.game_state).unwrap()
+        );
+    }
+
+    #[test]
+    fn saved_games_round_trip_and_continue_the_same_way() {
+        let mut game = Game::new(24, 24, &MapGenerationOptions::default(), 5, Some(9)).unwrap();
+        game.rules = GameRules {
+            action_resolution: ActionResolution::HeroFirst,
+            boundary: BoundaryPolicy::Solid,
+        };
+        game.game_map.set_topology(MapTopology::Torus);
+        for _ in 0..5 {
+            game.run_game_iteration();
+        }
+
+        let mut restored = Game::from_json(&game.to_json()).unwrap();
+        assert_same_game(&game, &restored);
+        while !game.is_over() && game.game_state.num_steps_run < 40 {
+            assert_eq!(game.run_game_iteration(), restored.run_game_iteration());
+            assert_same_game(&game, &restored);
+        }
+    }
+
+    #[test]
+    fn finished_games_reload_finished() {
+        let mut won = Game::from_map(Map::from_text("HG").unwrap(), 0, Some(1)).unwrap();
+        while !won.is_over() && won.game_state.num_steps_run < 10 {
+            won.run_game_iteration();
+        }
+        let mut lost = Game::from_map(Map::from_text("H.G").unwrap(), 0, Some(1)).unwrap();
+        lost.run_game_iteration_with_hero_action(EntityAction::HonorableSuicide);
+
+        for (game, running_state) in [
+            (won, GameRunningState::HeroVictory),
+            (lost, GameRunningState::HeroFailure),
+        ] {
+            assert_eq!(game.game_state.running_state, running_state);
+            let restored = Game::from_json(&game.to_json()).unwrap();
+            assert!(restored.is_over());
+            assert_same_game(&game, &restored);
+        }
+    }
+
+    #[test]
+    fn unknown_formats_and_versions_are_rejected() {
+        let game = Game::from_map(Map::from_text("H.G").unwrap(), 0, Some(1)).unwrap();
+        let mut saved = serde_json::to_value(SavedGame::from_game(&game)).unwrap();
+
+        saved["version"] = serde_json::json!(SAVE_FORMAT_VERSION + 1);
+        assert!(matches!(
+            Game::from_json(&saved.to_string()),
+            Err(SaveError::UnsupportedVersion { version, .. }) if version == SAVE_FORMAT_VERSION + 1
+        ));
+
+        saved["version"] = serde_json::json!(SAVE_FORMAT_VERSION);
+        saved["format"] = serde_json::json!("flatland-replay");
+        assert!(matches!(
+            Game::from_json(&saved.to_string()),
+            Err(SaveError::UnsupportedVersion { format, .. }) if format == "flatland-replay"
+        ));
+
+        assert!(matches!(
+            Game::from_json("not a save"),
+            Err(SaveError::InvalidJson(_))
+        ));
+    }
+}
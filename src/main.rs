@@ -1,5 +1,6 @@
 #![recursion_limit = "128"]
 
+use std::rc::Rc;
 use std::{thread, time::Duration};
 
 use console_error_panic_hook::set_once as set_panic_hook;
@@ -7,37 +8,490 @@ use wasm_bindgen::prelude::*;
 use web_sys::{CanvasRenderingContext2d, Element, HtmlCanvasElement, js_sys::Function, window};
 
 use crate::{
-    game::{Game, GameRunningState},
-    map::MapItem,
+    game::{
+        ActionResolution, BoundaryPolicy, EntityAction, Game, GameRules, GameRunningState, Replay,
+        ReplayPlayer,
+    },
+    map::{
+        CaveGenerator, DungeonGenerator, EnemyPlacement, GoalPlacement, Map, MapGenerationOptions,
+        MapGeneratorRegistry, MapItem, MapSymmetry, MapTopology, MazeAlgorithm, MazeGenerator,
+        PolyominoLibrary, RoomPlacement, SymmetricSpawns, WfcGenerator,
+    },
 };
 
 mod active_entity;
+mod benchmark;
 mod game;
 mod map;
 
+/// Largest canvas edge, in pixels, that `WasmGame` will size the canvas to.
+const MAX_CANVAS_DIM_PX: usize = 640;
+
+/// Turn an error into an exception for JS, prefixed with what was being attempted.
+fn js_error(context: &str, error: impl std::fmt::Debug) -> JsValue {
+    return JsValue::from_str(&format!("{}: {:?}", context, error));
+}
+
+/// Everything needed to start a `WasmGame`. Build one from JS, adjust it with the setters and
+/// pass it to `WasmGame.new`.
+#[wasm_bindgen]
+struct WasmGameOptions {
+    width: usize,
+    height: usize,
+    num_teleports: u32,
+    seed: Option<u64>,
+    map_options: MapGenerationOptions,
+}
+
+#[wasm_bindgen]
+impl WasmGameOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        width: usize,
+        height: usize,
+        fill_ratio: f32,
+        num_enemies: usize,
+        num_teleports: u32,
+    ) -> WasmGameOptions {
+        return WasmGameOptions {
+            width,
+            height,
+            num_teleports,
+            seed: None,
+            map_options: MapGenerationOptions {
+                wall_coverage: fill_ratio,
+                num_enemies,
+                ..Default::default()
+            },
+        };
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// How far the scattered-obstacle generator may land from the requested fill ratio.
+    pub fn set_coverage_tolerance(&mut self, coverage_tolerance: f32) {
+        self.map_options.coverage_tolerance = coverage_tolerance;
+    }
+
+    /// Keep enemies at least this many cells (Manhattan distance) away from the hero.
+    pub fn set_min_hero_enemy_distance(&mut self, distance: u32) {
+        self.map_options.placement.min_hero_enemy_distance = distance;
+    }
+
+    /// Keep the goal at least this many steps away from the hero along open cells.
+    pub fn set_min_hero_goal_distance(&mut self, distance: u32) {
+        self.map_options.placement.min_hero_goal_path_distance = distance;
+    }
+
+    /// Place the goal "uniform"ly at random or at the "farthest" reachable cell from the hero.
+    pub fn set_goal_placement(&mut self, name: &str) -> Result<(), JsValue> {
+        self.map_options.placement.goal_placement = GoalPlacement::from_name(name)
+            .ok_or_else(|| js_error("Unknown goal placement", name))?;
+        return Ok(());
+    }
+
+    /// Make the map "left_right", "top_bottom", "rotate180" or "rotate90" symmetric (or
+    /// "none"), and either "swap" the hero and goal or put the goal on the symmetry "axis".
+    pub fn set_symmetry(&mut self, symmetry: &str, spawns: &str) -> Result<(), JsValue> {
+        self.map_options.symmetry = MapSymmetry::from_name(symmetry)
+            .ok_or_else(|| js_error("Unknown symmetry", symmetry))?;
+        self.map_options.symmetric_spawns = SymmetricSpawns::from_name(spawns)
+            .ok_or_else(|| js_error("Unknown symmetric spawns", spawns))?;
+        return Ok(());
+    }
+
+    /// Generate the map for a `bounded` world or one whose edges wrap into a `torus`.
+    pub fn set_topology(&mut self, name: &str) -> Result<(), JsValue> {
+        self.map_options.topology =
+            MapTopology::from_name(name).ok_or_else(|| js_error("Unknown topology", name))?;
+        return Ok(());
+    }
+
+    /// Place enemies uniformly at random (the default).
+    pub fn use_uniform_enemies(&mut self) {
+        self.map_options.placement.enemy_placement = EnemyPlacement::Uniform;
+    }
+
+    /// Spread enemies so that no two are closer than `min_spacing` cells.
+    pub fn use_spread_enemies(&mut self, min_spacing: f32) {
+        self.map_options.placement.enemy_placement = EnemyPlacement::Spread { min_spacing };
+    }
+
+    /// Cluster enemies within `radius` steps of the goal.
+    pub fn use_goal_guard_enemies(&mut self, radius: u32) {
+        self.map_options.placement.enemy_placement = EnemyPlacement::GuardGoal { radius };
+    }
+
+    /// Select an obstacle generator by name, with its default settings. See
+    /// `MapGeneratorRegistry` for the names.
+    pub fn use_generator(&mut self, name: &str) -> Result<(), JsValue> {
+        return self
+            .map_options
+            .select_generator(name, &MapGeneratorRegistry::default())
+            .map_err(|_| js_error("Unknown generator", name));
+    }
+
+    /// Scatter tetromino obstacles (the default).
+    pub fn use_tetromino_generator(&mut self) {
+        self.map_options.obstacle_generator = Rc::new(PolyominoLibrary::default());
+    }
+
+    /// Scatter obstacles from one of the built-in profiles in `obstacle_profiles/`, such as
+    /// "tetrominoes", "pentominoes" or "walls".
+    pub fn use_polyomino_profile(&mut self, name: &str) -> Result<(), JsValue> {
+        let library = PolyominoLibrary::built_in(name)
+            .ok_or_else(|| js_error("Unknown obstacle profile", name))?;
+        self.map_options.obstacle_generator = Rc::new(library);
+        return Ok(());
+    }
+
+    /// Scatter obstacles from a shape library written in the obstacle profile text format.
+    /// Throws if the library doesn't parse.
+    pub fn use_polyomino_library(&mut self, library_text: &str) -> Result<(), JsValue> {
+        let library = PolyominoLibrary::parse(library_text)
+            .map_err(|error| js_error("Shape library didn't parse", error))?;
+        self.map_options.obstacle_generator = Rc::new(library);
+        return Ok(());
+    }
+
+    /// Carve a maze with `algorithm` ("backtracker" or "prim"), opening `loop_ratio` of its
+    /// dead ends.
+    pub fn use_maze_generator(&mut self, algorithm: &str, loop_ratio: f32) -> Result<(), JsValue> {
+        self.map_options.obstacle_generator = Rc::new(MazeGenerator {
+            algorithm: MazeAlgorithm::from_name(algorithm)
+                .ok_or_else(|| js_error("Unknown maze algorithm", algorithm))?,
+            loop_ratio,
+        });
+        return Ok(());
+    }
+
+    /// Grow a cellular-automata cave. See `CaveGenerator` for what each parameter does.
+    pub fn use_cave_generator(
+        &mut self,
+        fill: f32,
+        iterations: u32,
+        birth_threshold: u8,
+        survival_threshold: u8,
+    ) {
+        self.map_options.obstacle_generator = Rc::new(CaveGenerator {
+            fill,
+            iterations,
+            birth_threshold,
+            survival_threshold,
+        });
+    }
+
+    /// Lay out rooms with `placement` ("bsp" or "random") and join them with corridors.
+    /// See `DungeonGenerator` for what each parameter does.
+    pub fn use_dungeon_generator(
+        &mut self,
+        placement: &str,
+        min_room_size: usize,
+        max_room_size: usize,
+        corridor_width: usize,
+        max_rooms: usize,
+    ) -> Result<(), JsValue> {
+        self.map_options.obstacle_generator = Rc::new(DungeonGenerator {
+            placement: RoomPlacement::from_name(placement)
+                .ok_or_else(|| js_error("Unknown room placement", placement))?,
+            min_room_size,
+            max_room_size,
+            corridor_width,
+            max_rooms,
+        });
+        return Ok(());
+    }
+
+    /// Synthesize obstacles with Wave Function Collapse from one of the examples in
+    /// `wfc_examples/`, learning `pattern_size` by `pattern_size` patterns.
+    pub fn use_wfc_example(&mut self, name: &str, pattern_size: usize) -> Result<(), JsValue> {
+        let generator = WfcGenerator::built_in(name, pattern_size)
+            .ok_or_else(|| js_error("Unknown WFC example", name))?;
+        self.map_options.obstacle_generator = Rc::new(generator);
+        return Ok(());
+    }
+
+    /// Synthesize obstacles with Wave Function Collapse from an example map given as text.
+    /// Throws if the example doesn't parse.
+    pub fn use_wfc_example_text(
+        &mut self,
+        example: &str,
+        pattern_size: usize,
+    ) -> Result<(), JsValue> {
+        let generator = WfcGenerator::from_example(example, pattern_size)
+            .map_err(|error| js_error("WFC example didn't parse", error))?;
+        self.map_options.obstacle_generator = Rc::new(generator);
+        return Ok(());
+    }
+}
+
 #[wasm_bindgen]
 struct WasmGame {
-    game: Game<64, 64>,
+    game: Game,
+    /// Set when playing back a replay; `game` then shows the replay's current step.
+    player: Option<ReplayPlayer>,
     canvas: Option<HtmlCanvasElement>,
+    cell_square_dim_px: f64,
 }
 
-#[wasm_bindgen]
 impl WasmGame {
-    pub fn new(fill_ratio: f32, num_enemies: usize, num_teleports: u32) -> WasmGame {
+    /// Size the page's canvas to fit the game's map and wrap the game for JS. The game is
+    /// recorded from here on so it can be saved as a replay.
+    fn attach_to_canvas(mut game: Game) -> WasmGame {
+        game.start_recording();
         let window = window().expect("Could not access window.");
         let document = window.document().expect("Could not access document.");
-        let body = document.body().expect("Could not access document.body.");
         let canvas = document
             .get_element_by_id("flatland_canvas")
-            .expect("Canvas should exist!");
+            .expect("Canvas should exist!")
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .unwrap();
+        let [width, height] = [game.game_map.dimx(), game.game_map.dimy()];
+
+        // Fit the whole map into the canvas, but never draw a cell smaller than a pixel.
+        let cell_square_dim_px = usize::max(1, MAX_CANVAS_DIM_PX / usize::max(width, height));
+        canvas.set_width((width * cell_square_dim_px) as u32);
+        canvas.set_height((height * cell_square_dim_px) as u32);
+
         return WasmGame {
-            game: Game::new(fill_ratio, num_enemies, num_teleports)
-                .expect("Game should generate properly"),
-            canvas: Some(canvas.dyn_into::<web_sys::HtmlCanvasElement>().unwrap()),
+            game,
+            player: None,
+            canvas: Some(canvas),
+            cell_square_dim_px: cell_square_dim_px as f64,
         };
     }
 
+    fn replay_player(&self) -> &ReplayPlayer {
+        return self.player.as_ref().expect("Not playing a replay!");
+    }
+
+    fn replay_player_mut(&mut self) -> &mut ReplayPlayer {
+        return self.player.as_mut().expect("Not playing a replay!");
+    }
+
+    /// Copy the replay's current step into `game` so it is rendered.
+    fn show_replay_step(&mut self) {
+        self.game = self.replay_player().game().clone();
+    }
+}
+
+#[wasm_bindgen]
+impl WasmGame {
+    /// Generate a new game. Throws if no map fits the options, for example when the goal
+    /// stays unreachable or the placement constraints can't be met.
+    pub fn new(options: &WasmGameOptions) -> Result<WasmGame, JsValue> {
+        let game = Game::new(
+            options.width,
+            options.height,
+            &options.map_options,
+            options.num_teleports,
+            options.seed,
+        )
+        .map_err(|error| js_error("Map didn't generate", error))?;
+        return Ok(WasmGame::attach_to_canvas(game));
+    }
+
+    /// Start a game on a level in the plain-text map format (see `Map::from_text`). Throws if
+    /// the level doesn't parse or isn't playable.
+    pub fn from_text(
+        level_text: &str,
+        num_teleports: u32,
+        seed: Option<u64>,
+    ) -> Result<WasmGame, JsValue> {
+        let game_map =
+            Map::from_text(level_text).map_err(|error| js_error("Level didn't parse", error))?;
+        let game = Game::from_map(game_map, num_teleports, seed)
+            .map_err(|error| js_error("Level isn't playable", error))?;
+        return Ok(WasmGame::attach_to_canvas(game));
+    }
+
+    /// Start a game on a level designed in the Tiled map editor (see `Map::from_tiled_json`).
+    /// Throws if the level doesn't load or isn't playable.
+    pub fn from_tiled(
+        level_json: &str,
+        num_teleports: u32,
+        seed: Option<u64>,
+    ) -> Result<WasmGame, JsValue> {
+        let game_map = Map::from_tiled_json(level_json)
+            .map_err(|error| js_error("Tiled level didn't load", error))?;
+        let game = Game::from_map(game_map, num_teleports, seed)
+            .map_err(|error| js_error("Level isn't playable", error))?;
+        return Ok(WasmGame::attach_to_canvas(game));
+    }
+
+    /// Resume a game saved with `save_json`. Throws if the save file can't be read.
+    pub fn load_json(save_json: &str) -> Result<WasmGame, JsValue> {
+        let game =
+            Game::from_json(save_json).map_err(|error| js_error("Save file didn't load", error))?;
+        return Ok(WasmGame::attach_to_canvas(game));
+    }
+
+    /// Make the world's edges `bounded` or wrap them into a `torus`. Like the other settings,
+    /// this restarts the game's replay recording from the current step.
+    pub fn set_topology(&mut self, name: &str) -> Result<(), JsValue> {
+        let topology =
+            MapTopology::from_name(name).ok_or_else(|| js_error("Unknown topology", name))?;
+        self.game.set_topology(topology);
+        return Ok(());
+    }
+
+    /// Choose how each step's actions are resolved: `shuffle`, `hero_first`, `enemies_first`
+    /// or `simultaneous`.
+    pub fn set_action_resolution(&mut self, name: &str) -> Result<(), JsValue> {
+        self.game.set_rules(GameRules {
+            action_resolution: ActionResolution::from_name(name)
+                .ok_or_else(|| js_error("Unknown action resolution", name))?,
+            ..self.game.rules
+        });
+        return Ok(());
+    }
+
+    /// Choose what the map's edges do to entities that move off them: `solid` or `lethal`.
+    /// Use `set_topology` for edges that wrap around.
+    pub fn set_boundary(&mut self, name: &str) -> Result<(), JsValue> {
+        self.game.set_rules(GameRules {
+            boundary: BoundaryPolicy::from_name(name)
+                .ok_or_else(|| js_error("Unknown boundary policy", name))?,
+            ..self.game.rules
+        });
+        return Ok(());
+    }
+
+    /// Everything played so far as a replay file, to be played back with `load_replay`.
+    pub fn save_replay(&self) -> String {
+        if let Some(player) = &self.player {
+            return player.replay().to_json();
+        }
+        return self
+            .game
+            .replay()
+            .expect("Games are recorded from the start")
+            .to_json();
+    }
+
+    /// Open a replay saved with `save_replay`. `update_flatland` then plays the recorded steps
+    /// instead of simulating new ones. Throws if the replay file can't be read.
+    pub fn load_replay(replay_json: &str) -> Result<WasmGame, JsValue> {
+        let replay = Replay::from_json(replay_json)
+            .map_err(|error| js_error("Replay file didn't load", error))?;
+        let player = ReplayPlayer::new(replay)
+            .map_err(|error| js_error("Replay doesn't start from a valid game", error))?;
+        let mut wasm_game = WasmGame::attach_to_canvas(player.game().clone());
+        wasm_game.player = Some(player);
+        return Ok(wasm_game);
+    }
+
+    /// Number of steps in the replay being played back.
+    pub fn replay_length(&self) -> usize {
+        return self.replay_player().step_count();
+    }
+
+    /// The step of the replay currently shown.
+    pub fn replay_step(&self) -> usize {
+        return self.replay_player().current_step();
+    }
+
+    /// Show the replay as it was after `step` steps.
+    pub fn replay_seek(&mut self, step: usize) {
+        self.replay_player_mut().seek(step);
+        self.show_replay_step();
+    }
+
+    /// Show the next step of the replay and return its events as a JSON array (empty at the
+    /// end).
+    pub fn replay_step_forward(&mut self) -> String {
+        let events = self.replay_player_mut().step_forward().unwrap_or_default();
+        self.show_replay_step();
+        return serde_json::to_string(&events).expect("Events always serialize");
+    }
+
+    /// Show the previous step of the replay.
+    pub fn replay_step_back(&mut self) {
+        self.replay_player_mut().step_back();
+        self.show_replay_step();
+    }
+
+    /// Number of steps run so far.
+    pub fn current_step(&self) -> u32 {
+        return self.game.game_state.num_steps_run;
+    }
+
+    /// The earliest step `rewind_to` can go back to.
+    pub fn earliest_step(&self) -> u32 {
+        return self.game.earliest_step();
+    }
+
+    /// Undo the last step. Returns false if there is nothing to undo. `update_flatland` then
+    /// runs the same step again.
+    pub fn step_back(&mut self) -> bool {
+        return self.game.step_back();
+    }
+
+    /// Go back to the game as it was after `step` steps. Returns false if that step is no
+    /// longer in the history.
+    pub fn rewind_to(&mut self, step: u32) -> bool {
+        return self.game.rewind_to(step);
+    }
+
+    /// Run one step with the hero doing `action` (`none`, `up`, `down`, `left`, `right`,
+    /// `teleport` or `suicide`) instead of its own choice, and return its events as a JSON
+    /// array. Used after stepping back to try something else.
+    pub fn branch_with_hero_action(&mut self, action: &str) -> String {
+        let action = EntityAction::from_name(action).expect("Unknown hero action!");
+        let events = self.game.run_game_iteration_with_hero_action(action);
+        return serde_json::to_string(&events).expect("Events always serialize");
+    }
+
+    /// The whole game as versioned JSON, to be resumed later with `load_json`.
+    pub fn save_json(&self) -> String {
+        return self.game.to_json();
+    }
+
+    /// The current map in the one-character-per-cell ASCII format.
+    pub fn map_text(&self) -> String {
+        return self
+            .game
+            .game_map
+            .generate_ascii_string()
+            .expect("Map should exist");
+    }
+
+    /// The seed this game was generated from. Setting it on the same options reproduces
+    /// the same run.
+    pub fn seed(&self) -> u64 {
+        return self.game.game_state.seed;
+    }
+
+    /// Difficulty metrics for the current map (see `Map::difficulty_report`) as JSON, or
+    /// `null` once the hero or goal has left the map.
+    pub fn difficulty_report(&self) -> String {
+        return serde_json::to_string(&self.game.game_map.difficulty_report())
+            .expect("Reports always serialize");
+    }
+
+    /// Fraction of the map's cells that are obstacles, as actually generated.
+    pub fn obstacle_coverage(&self) -> f32 {
+        return self
+            .game
+            .game_map
+            .obstacle_coverage()
+            .expect("Map should exist");
+    }
+
+    pub fn width(&self) -> usize {
+        return self.game.game_map.dimx();
+    }
+
+    pub fn height(&self) -> usize {
+        return self.game.game_map.dimy();
+    }
+
     pub fn is_game_over(&self) -> bool {
+        if self.player.as_ref().is_some_and(|player| player.at_end()) {
+            return true;
+        }
         match self.game.game_state.running_state {
             GameRunningState::NotStarted => {
                 return false;
@@ -54,8 +508,14 @@ impl WasmGame {
         }
     }
 
-    pub fn update_flatland(&mut self) {
-        self.game.run_game_iteration();
+    /// Run one step, or play the next one of a replay, and return its events (see
+    /// `GameEvent`) as a JSON array.
+    pub fn update_flatland(&mut self) -> String {
+        if self.player.is_some() {
+            return self.replay_step_forward();
+        }
+        let events = self.game.run_game_iteration();
+        return serde_json::to_string(&events).expect("Events always serialize");
     }
 
     pub fn render(&self) {
@@ -67,11 +527,12 @@ impl WasmGame {
             .dyn_into::<CanvasRenderingContext2d>()
             .unwrap();
         ctx.set_fill_style_str("white");
-        ctx.fill_rect(0., 0., 640., 640.);
-        let cell_square_dim_px = 10.;
-        for row_idx in 0..64 {
-            for col_idx in 0..64 {
-                let mapitem: MapItem = self.game.game_map.map.unwrap()[row_idx][col_idx];
+        ctx.fill_rect(0., 0., canvas.width() as f64, canvas.height() as f64);
+        let cell_square_dim_px = self.cell_square_dim_px;
+        let map = self.game.game_map.map.as_ref().expect("Map should exist");
+        for row_idx in 0..self.game.game_map.dimy() {
+            for col_idx in 0..self.game.game_map.dimx() {
+                let mapitem: MapItem = map[col_idx][row_idx];
                 match mapitem {
                     MapItem::Empty => {
                         ctx.set_fill_style_str("#FFFFFF");
@@ -101,22 +562,22 @@ impl WasmGame {
                         );
                     }
                     MapItem::EnemyEntity => {
-                        ctx.set_font("10px sans-serif");
+                        ctx.set_font(&format!("{}px sans-serif", cell_square_dim_px));
                         let x_pos = col_idx as f64 * cell_square_dim_px;
                         let y_pos = row_idx as f64 * cell_square_dim_px;
                         ctx.set_fill_style_str("#FFFFFF");
                         ctx.fill_rect(x_pos, y_pos, cell_square_dim_px, cell_square_dim_px);
                         ctx.set_fill_style_str("#FF0000");
-                        let _ = ctx.fill_text(&"\u{25B2}", x_pos, y_pos + 8.);
+                        let _ = ctx.fill_text(&"\u{25B2}", x_pos, y_pos + cell_square_dim_px * 0.8);
                     }
                     MapItem::HeroEntity => {
-                        ctx.set_font("8px sans-serif");
+                        ctx.set_font(&format!("{}px sans-serif", cell_square_dim_px * 0.8));
                         let x_pos = col_idx as f64 * cell_square_dim_px;
                         let y_pos = row_idx as f64 * cell_square_dim_px;
                         ctx.set_fill_style_str("#FFFFFF");
                         ctx.fill_rect(x_pos, y_pos, cell_square_dim_px, cell_square_dim_px);
                         ctx.set_fill_style_str("#0000FF");
-                        let _ = ctx.fill_text("\u{2B24}", x_pos, y_pos + 8.);
+                        let _ = ctx.fill_text("\u{2B24}", x_pos, y_pos + cell_square_dim_px * 0.8);
                     }
                     MapItem::Goal => {
                         ctx.set_fill_style_str("#00FF00");
@@ -191,4 +652,11 @@ impl WasmGame {
 
 fn main() {
     set_panic_hook();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if !args.is_empty() {
+            benchmark::run_cli(&args);
+        }
+    }
 }
@@ -1,11 +1,43 @@
 // Map definitions
 
 use rand::Rng;
-use rand::distr::Distribution;
-use rand::distr::slice::Choose;
-use std::collections::HashMap;
-
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+mod analysis;
+mod cave;
+mod dungeon;
+mod editor;
+mod generator;
+mod maze;
+mod movingai;
+mod placement;
+mod polyomino;
+mod ros;
+mod symmetry;
+mod text_format;
+mod tiled;
+mod wfc;
+
+pub use analysis::{DifficultyReport, NEAR_PATH_RADIUS};
+pub use cave::CaveGenerator;
+pub use dungeon::{DungeonGenerator, RoomPlacement};
+pub use editor::{MapEditError, MapEditor};
+pub use generator::{MapGenerator, MapGeneratorRegistry};
+pub use maze::{MazeAlgorithm, MazeGenerator};
+pub use movingai::{MovingAiError, MovingAiScenario, parse_movingai_scenarios};
+pub use placement::{EnemyPlacement, GoalPlacement, PlacementOptions};
+pub use polyomino::{Polyomino, PolyominoLibrary, PolyominoParseError};
+pub use ros::{RosMapError, RosMapMetadata};
+pub use symmetry::{MapSymmetry, SymmetricSpawns};
+pub use text_format::MapParseError;
+pub use tiled::TiledError;
+pub use wfc::WfcGenerator;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum MapItem {
     Empty,
     Obstacle,
@@ -20,11 +52,105 @@ pub enum MapGenerationError {
     RanOutOfSpace,
     PrevOpFailed,
     InvalidWallCoverage,
+    GoalUnreachable,
+    InvalidGeneratorParameters,
+    CoverageUnreachable,
+    PlacementConstraintsUnsatisfiable,
+    /// Wave Function Collapse contradicted itself on every one of its attempts.
+    WfcContradiction {
+        attempts: u32,
+    },
+    /// No generator is registered under the requested name.
+    UnknownGenerator,
+}
+
+/// How many hero positions to try before giving up on the hero-goal placement constraints.
+const MAX_HERO_PLACEMENT_ATTEMPTS: u32 = 100;
+
+/// How `Map::generate_map` makes sure the hero can actually reach the goal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GoalConnectivity {
+    /// Place the hero and goal without checking for a path between them.
+    Unchecked,
+    /// Regenerate the whole map until the goal is reachable and the placement constraints are
+    /// met, giving up after `max_attempts`.
+    Retry { max_attempts: u32 },
+    /// Dig through the fewest obstacles needed to join the hero to the goal.
+    Carve,
+}
+
+/// Parameters for `Map::generate_map`.
+#[derive(Clone, Debug)]
+pub struct MapGenerationOptions {
+    pub wall_coverage: f32,
+    /// How far the measured obstacle fraction may be from `wall_coverage`.
+    pub coverage_tolerance: f32,
+    pub num_enemies: usize,
+    pub connectivity: GoalConnectivity,
+    /// Lays out the obstacles; see `MapGenerator`.
+    pub obstacle_generator: Rc<dyn MapGenerator>,
+    pub placement: PlacementOptions,
+    /// Mirror or rotational symmetry applied on top of any obstacle generator.
+    pub symmetry: MapSymmetry,
+    /// Where the hero and goal go when `symmetry` isn't `MapSymmetry::None`.
+    pub symmetric_spawns: SymmetricSpawns,
+    /// How the generated map's edges behave. Connectivity checks, carving and entity spacing
+    /// all measure distances the way the game will be played.
+    pub topology: MapTopology,
+}
+
+impl Default for MapGenerationOptions {
+    fn default() -> MapGenerationOptions {
+        MapGenerationOptions {
+            wall_coverage: 0.25,
+            coverage_tolerance: 0.005,
+            num_enemies: 20,
+            connectivity: GoalConnectivity::Retry { max_attempts: 100 },
+            obstacle_generator: Rc::new(PolyominoLibrary::default()),
+            placement: PlacementOptions::default(),
+            symmetry: MapSymmetry::None,
+            symmetric_spawns: SymmetricSpawns::SwapHeroAndGoal,
+            topology: MapTopology::Bounded,
+        }
+    }
+}
+
+/// How the edges of the world behave.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MapTopology {
+    /// The grid ends at its edges.
+    #[default]
+    Bounded,
+    /// The edges wrap around: leaving one side enters the opposite one.
+    Torus,
+}
+
+impl MapTopology {
+    /// Look up a topology by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<MapTopology> {
+        match name {
+            "bounded" => {
+                return Some(MapTopology::Bounded);
+            }
+            "torus" => {
+                return Some(MapTopology::Torus);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
 }
 
+/// Cell storage for a map, indexed as `grid[x][y]`.
+pub type MapGrid = Vec<Vec<MapItem>>;
+
 #[derive(Clone)]
-pub struct Map<const DIMX: usize, const DIMY: usize> {
-    pub map: Option<[[MapItem; DIMY]; DIMX]>,
+pub struct Map {
+    pub map: Option<MapGrid>,
+    dimx: usize,
+    dimy: usize,
+    topology: MapTopology,
     unicode_mappings: HashMap<MapItem, char>,
 }
 
@@ -42,169 +168,263 @@ impl MapItem {
 
         return mappings;
     }
+
+    /// Whether an entity can walk through this cell (the same rule `get_empty_neighbors` uses).
+    pub fn is_traversable(&self) -> bool {
+        match self {
+            MapItem::Empty | MapItem::Goal | MapItem::HeroEntity | MapItem::EnemyEntity => {
+                return true;
+            }
+            MapItem::Obstacle | MapItem::Junk => {
+                return false;
+            }
+        }
+    }
 }
 
-impl<const DIMX: usize, const DIMY: usize> Map<DIMX, DIMY> {
-    /// Create a new Map object.
-    pub fn new() -> Map<DIMX, DIMY> {
-        assert!(DIMX > 0 && DIMY > 0);
+impl Map {
+    /// Create a new Map object with the given width (x) and height (y) in cells.
+    pub fn new(dimx: usize, dimy: usize) -> Map {
+        assert!(dimx > 0 && dimy > 0);
         Map {
             map: Option::None,
+            dimx,
+            dimy,
+            topology: MapTopology::Bounded,
             unicode_mappings: MapItem::generate_default_unicode_mappings(),
         }
     }
 
-    /// Generate a map with approximate % coverage using a tetromino-based algorithm.
-    /// Then, fill the map with n enemies, place the hero, and finally place the goal.
-    pub fn generate_map(
+    /// How the edges of the map behave. New maps are bounded.
+    pub fn topology(&self) -> MapTopology {
+        return self.topology;
+    }
+
+    pub fn set_topology(&mut self, topology: MapTopology) {
+        self.topology = topology;
+    }
+
+    /// The cell `delta` away from `position`, wrapping around the edges on a torus.
+    /// Returns None if the move would leave a bounded map.
+    pub fn offset_position(
         &self,
-        wall_coverage: f32,
-        num_enemies: usize,
-    ) -> Result<[[MapItem; DIMY]; DIMX], MapGenerationError> {
-        let mut new_map = self.generate_map_with_obstacles(wall_coverage);
-        if new_map.is_err() {
-            return new_map;
+        position: [usize; 2],
+        [delta_x, delta_y]: [i32; 2],
+    ) -> Option<[usize; 2]> {
+        let tx = position[0] as i32 + delta_x;
+        let ty = position[1] as i32 + delta_y;
+        match self.topology {
+            MapTopology::Bounded => {
+                if tx < self.dimx as i32 && tx >= 0 && ty < self.dimy as i32 && ty >= 0 {
+                    return Some([tx as usize, ty as usize]);
+                }
+                return None;
+            }
+            MapTopology::Torus => {
+                return Some([
+                    tx.rem_euclid(self.dimx as i32) as usize,
+                    ty.rem_euclid(self.dimy as i32) as usize,
+                ]);
+            }
         }
-        new_map = self.add_enemies_to_map(new_map.ok(), num_enemies);
-        if new_map.is_err() {
-            return new_map;
+    }
+
+    /// Shortest signed step counts from `from` to `to` along x and y. On a torus this may go
+    /// the other way around an edge.
+    pub fn displacement(&self, from: [usize; 2], to: [usize; 2]) -> [i32; 2] {
+        let mut delta = [to[0] as i32 - from[0] as i32, to[1] as i32 - from[1] as i32];
+        if self.topology == MapTopology::Torus {
+            for (axis, dim) in [self.dimx as i32, self.dimy as i32].into_iter().enumerate() {
+                if delta[axis] > dim / 2 {
+                    delta[axis] -= dim;
+                } else if delta[axis] < -dim / 2 {
+                    delta[axis] += dim;
+                }
+            }
         }
-        return self.add_hero_and_goal_to_map(new_map.ok());
+        return delta;
     }
 
-    /// Create a map and fill it with obstacles using a tetromino-based coverage algorithm.
-    fn generate_map_with_obstacles(
-        &self,
-        wall_coverage: f32,
-    ) -> Result<[[MapItem; DIMY]; DIMX], MapGenerationError> {
-        let mut working_map: [[MapItem; DIMY]; DIMX] = [[MapItem::Empty; DIMY]; DIMX];
+    /// Width of the map in cells.
+    pub fn dimx(&self) -> usize {
+        return self.dimx;
+    }
 
-        if wall_coverage.clamp(0., 1.) != wall_coverage {
-            return Err(MapGenerationError::InvalidWallCoverage);
-        }
+    /// Height of the map in cells.
+    pub fn dimy(&self) -> usize {
+        return self.dimy;
+    }
 
-        struct Tetromino {
-            shape: Vec<Vec<bool>>,
+    /// Create a grid of this map's size where every cell holds `item`.
+    pub fn filled_grid(&self, item: MapItem) -> MapGrid {
+        return vec![vec![item; self.dimy]; self.dimx];
+    }
+
+    /// Generate a map's obstacles with the selected generator (by default, approximate % coverage
+    /// using scattered tetrominoes).
+    /// Then, place the hero, place the goal, and finally fill the map with n enemies.
+    /// The hero-to-goal path is checked, retried or carved according to `options.connectivity`.
+    /// Distances follow `options.topology`, whatever this map's own topology is.
+    pub fn generate_map(
+        &self,
+        options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if options.topology != self.topology {
+            let mut planning_map = self.clone();
+            planning_map.set_topology(options.topology);
+            return planning_map.generate_map(options, rng);
         }
-        let mut tet_options: Vec<Tetromino> = Vec::new();
-        tet_options.push(Tetromino {
-            shape: vec![vec![false, false, false, false]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![false, true, true], vec![false, false, false]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![false, false, false], vec![false, true, true]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![false, false, true], vec![true, false, false]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![true, false, false], vec![false, false, true]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![true, false, true], vec![false, false, false]],
-        });
-        tet_options.push(Tetromino {
-            shape: vec![vec![false, false], vec![false, false]],
-        });
-        let tets_dist = Choose::new(&tet_options).unwrap();
-
-        let num_tetrominos: usize = ((DIMX * DIMY) as f32 * wall_coverage / 4.) as usize;
-
-        let tets: Vec<&Tetromino> = tets_dist
-            .sample_iter(&mut rand::rng())
-            .take(num_tetrominos)
-            .collect();
-
-        for idx in 0..num_tetrominos {
-            let tet = tets[idx as usize];
-            let rotation = rand::random_range(0..=3);
-
-            match rotation {
-                0 => {
-                    // no rotation
-                    let pos = [
-                        rand::random_range(0..=DIMX - tet.shape.len()),
-                        rand::random_range(0..=DIMY - tet.shape[0].len()),
-                    ];
-                    for i in 0..tet.shape.len() {
-                        for j in 0..tet.shape[0].len() {
-                            if tet.shape[i][j] != true {
-                                working_map[pos[0] + i][pos[1] + j] = MapItem::Obstacle;
-                            }
-                        }
-                    }
-                }
-                1 => {
-                    // 180 degree rotation
-                    let pos = [
-                        rand::random_range(0..=DIMX - tet.shape.len()),
-                        rand::random_range(0..=DIMY - tet.shape[0].len()),
-                    ];
-                    for i in 0..tet.shape.len() {
-                        for j in 0..tet.shape[0].len() {
-                            if tet.shape[i][j] != true {
-                                working_map[pos[0] + tet.shape.len() - i - 1]
-                                    [pos[1] + tet.shape[0].len() - j - 1] = MapItem::Obstacle;
+        if options.symmetry != MapSymmetry::None {
+            self.check_symmetric_spawns(options.symmetry, options.symmetric_spawns)?;
+        }
+        match options.connectivity {
+            GoalConnectivity::Unchecked => {
+                return self.generate_unchecked_map(options, rng);
+            }
+            GoalConnectivity::Retry { max_attempts } => {
+                let mut last_error = MapGenerationError::GoalUnreachable;
+                for _ in 0..max_attempts {
+                    match self.generate_unchecked_map(options, rng) {
+                        Ok(new_map) => {
+                            if self.is_goal_reachable(&new_map) {
+                                return Ok(new_map);
                             }
+                            last_error = MapGenerationError::GoalUnreachable;
                         }
-                    }
-                }
-                2 => {
-                    // 90 degree rotation
-                    let pos = [
-                        rand::random_range(0..=DIMX - tet.shape[0].len()),
-                        rand::random_range(0..=DIMY - tet.shape.len()),
-                    ];
-                    for i in 0..tet.shape.len() {
-                        for j in 0..tet.shape[0].len() {
-                            if tet.shape[i][j] != true {
-                                working_map[pos[0] + tet.shape[0].len() - j - 1][pos[1] + i] =
-                                    MapItem::Obstacle;
-                            }
+                        // A different obstacle layout may leave room for the placement constraints.
+                        Err(MapGenerationError::PlacementConstraintsUnsatisfiable) => {
+                            last_error = MapGenerationError::PlacementConstraintsUnsatisfiable;
                         }
-                    }
-                }
-                3 => {
-                    // 270 degree rotation
-                    let pos = [
-                        rand::random_range(0..=DIMX - tet.shape[0].len()),
-                        rand::random_range(0..=DIMY - tet.shape.len()),
-                    ];
-                    for i in 0..tet.shape.len() {
-                        for j in 0..tet.shape[0].len() {
-                            if tet.shape[i][j] != true {
-                                working_map[pos[0] + j][pos[1] + tet.shape.len() - i - 1] =
-                                    MapItem::Obstacle;
-                            }
+                        Err(error) => {
+                            return Err(error);
                         }
                     }
                 }
-                _ => {
-                    unreachable!()
+                return Err(last_error);
+            }
+            GoalConnectivity::Carve => {
+                let mut new_map = self.generate_unchecked_map(options, rng)?;
+                self.carve_path_to_goal(&mut new_map);
+                if options.symmetry != MapSymmetry::None {
+                    // Dig the mirrored corridors too, so the carve doesn't break the symmetry.
+                    self.symmetrize_obstacles(&mut new_map, options.symmetry, true)?;
                 }
+                return Ok(new_map);
+            }
+        }
+    }
+
+    /// Run every generation step once without looking at hero-to-goal connectivity.
+    fn generate_unchecked_map(
+        &self,
+        options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        let mut new_map = options.obstacle_generator.generate(self, options, rng);
+        if new_map.is_err() {
+            return new_map;
+        }
+        if options.symmetry == MapSymmetry::None {
+            new_map = self.add_hero_and_goal_to_map(new_map.ok(), &options.placement, rng);
+            if new_map.is_err() {
+                return new_map;
             }
+            return self.add_enemies_to_map(
+                new_map.ok(),
+                options.num_enemies,
+                &options.placement,
+                rng,
+            );
         }
 
+        let mut working_map = new_map?;
+        self.symmetrize_obstacles(&mut working_map, options.symmetry, false)?;
+        new_map = self.add_symmetric_hero_and_goal(
+            Some(working_map),
+            options.symmetry,
+            options.symmetric_spawns,
+            &options.placement,
+            rng,
+        );
+        if new_map.is_err() {
+            return new_map;
+        }
+        let num_enemies = options
+            .num_enemies
+            .div_ceil(self.symmetry_orbit_size(options.symmetry));
+        let mut working_map =
+            self.add_enemies_to_map(new_map.ok(), num_enemies, &options.placement, rng)?;
+        self.mirror_enemies(&mut working_map, options.symmetry, &options.placement);
         return Ok(working_map);
     }
 
-    /// Add n enemies to the map and return a copy.
+    /// Add n enemies to the map, following the placement strategy, and return a copy.
     fn add_enemies_to_map(
         &self,
-        map_without_enemies: Option<[[MapItem; DIMY]; DIMX]>,
+        map_without_enemies: Option<MapGrid>,
         num_enemies: usize,
-    ) -> Result<[[MapItem; DIMY]; DIMX], MapGenerationError> {
+        placement: &PlacementOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
         if map_without_enemies.is_none() {
             return Err(MapGenerationError::PrevOpFailed);
         }
         let mut working_map = map_without_enemies.unwrap();
-        let mut unoccupied_positions = self.list_unoccupied_positions(working_map);
+        let mut unoccupied_positions = self.list_unoccupied_positions(&working_map);
+
+        if let Some(hero) = self.find_item(&working_map, MapItem::HeroEntity) {
+            let min_distance = placement.min_hero_enemy_distance;
+            unoccupied_positions.retain(|position| {
+                let [delta_x, delta_y] = self.displacement(hero, *position);
+                delta_x.unsigned_abs() + delta_y.unsigned_abs() >= min_distance
+            });
+        }
+
+        match placement.enemy_placement {
+            EnemyPlacement::Uniform => {}
+            EnemyPlacement::Spread { min_spacing } => {
+                // Dart throwing: visit the candidates in random order and keep each one that is
+                // far enough from every enemy kept so far.
+                unoccupied_positions.shuffle(rng);
+                let mut spread_positions: Vec<[usize; 2]> = Vec::new();
+                for candidate in unoccupied_positions {
+                    if spread_positions.len() == num_enemies {
+                        break;
+                    }
+                    let far_enough = spread_positions.iter().all(|placed| {
+                        let [delta_x, delta_y] = self.displacement(*placed, candidate);
+                        f32::hypot(delta_x as f32, delta_y as f32) >= min_spacing
+                    });
+                    if far_enough {
+                        spread_positions.push(candidate);
+                    }
+                }
+                if spread_positions.len() < num_enemies {
+                    return Err(MapGenerationError::PlacementConstraintsUnsatisfiable);
+                }
+                for [pos_x, pos_y] in spread_positions {
+                    working_map[pos_x][pos_y] = MapItem::EnemyEntity;
+                }
+                return Ok(working_map);
+            }
+            EnemyPlacement::GuardGoal { radius } => {
+                let Some(goal) = self.find_item(&working_map, MapItem::Goal) else {
+                    return Err(MapGenerationError::PrevOpFailed);
+                };
+                let goal_distances = self.distance_field(&working_map, goal);
+                unoccupied_positions.retain(|[pos_x, pos_y]| {
+                    goal_distances[*pos_x][*pos_y].is_some_and(|distance| distance <= radius)
+                });
+                if unoccupied_positions.len() < num_enemies {
+                    return Err(MapGenerationError::PlacementConstraintsUnsatisfiable);
+                }
+            }
+        }
 
         for n_enemies_added in 0..num_enemies {
             if !unoccupied_positions.is_empty() {
-                let position_idx = rand::rng().random_range(0..unoccupied_positions.len());
+                let position_idx = rng.random_range(0..unoccupied_positions.len());
                 let [pos_x, pos_y] = unoccupied_positions.remove(position_idx);
                 working_map[pos_x][pos_y] = MapItem::EnemyEntity;
             } else {
@@ -218,52 +438,64 @@ impl<const DIMX: usize, const DIMY: usize> Map<DIMX, DIMY> {
         return Ok(working_map);
     }
 
-    /// Add the hero and the goal to the map at unoccupied positions.
+    /// Add the hero and the goal to the map at unoccupied positions, following the placement
+    /// strategy. If no goal fits the constraints for a hero position, another one is tried.
     fn add_hero_and_goal_to_map(
         &self,
-        map_without_hero_or_goal: Option<[[MapItem; DIMY]; DIMX]>,
-    ) -> Result<[[MapItem; DIMY]; DIMX], MapGenerationError> {
+        map_without_hero_or_goal: Option<MapGrid>,
+        placement: &PlacementOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
         if map_without_hero_or_goal.is_none() {
             return Err(MapGenerationError::PrevOpFailed);
         }
         let mut working_map = map_without_hero_or_goal.unwrap();
 
-        // yes I know I could just cache this value instead
-        let mut unoccupied_positions = self.list_unoccupied_positions(working_map);
-
-        if !unoccupied_positions.is_empty() {
-            let position_idx = rand::rng().random_range(0..unoccupied_positions.len());
-            let [pos_x, pos_y] = unoccupied_positions.remove(position_idx);
-            working_map[pos_x][pos_y] = MapItem::HeroEntity;
-        } else {
-            dbg!(
-                "Map ran out of space in add_hero_and_goal_to_map when adding the hero to the map."
-            );
+        let unoccupied_positions = self.list_unoccupied_positions(&working_map);
+        if unoccupied_positions.len() < 2 {
             return Err(MapGenerationError::RanOutOfSpace);
         }
+        let needs_distances = placement.min_hero_goal_path_distance > 0
+            || placement.goal_placement == GoalPlacement::Farthest;
+
+        for _ in 0..MAX_HERO_PLACEMENT_ATTEMPTS {
+            let hero = unoccupied_positions[rng.random_range(0..unoccupied_positions.len())];
+            let mut goal_candidates: Vec<[usize; 2]> = unoccupied_positions
+                .iter()
+                .filter(|position| **position != hero)
+                .copied()
+                .collect();
+
+            if needs_distances {
+                let hero_distances = self.distance_field(&working_map, hero);
+                let distance_of = |[pos_x, pos_y]: [usize; 2]| hero_distances[pos_x][pos_y];
+                goal_candidates.retain(|position| {
+                    distance_of(*position)
+                        .is_some_and(|distance| distance >= placement.min_hero_goal_path_distance)
+                });
+                if placement.goal_placement == GoalPlacement::Farthest {
+                    let farthest = goal_candidates.iter().filter_map(|p| distance_of(*p)).max();
+                    goal_candidates.retain(|position| distance_of(*position) == farthest);
+                }
+            }
+            if goal_candidates.is_empty() {
+                continue;
+            }
 
-        if !unoccupied_positions.is_empty() {
-            let position_idx = rand::rng().random_range(0..unoccupied_positions.len());
-            let [pos_x, pos_y] = unoccupied_positions.remove(position_idx);
-            working_map[pos_x][pos_y] = MapItem::Goal;
-        } else {
-            dbg!(
-                "Map ran out of space in add_hero_and_goal_to_map when adding the goal to the map."
-            );
-            return Err(MapGenerationError::RanOutOfSpace);
+            let goal = goal_candidates[rng.random_range(0..goal_candidates.len())];
+            working_map[hero[0]][hero[1]] = MapItem::HeroEntity;
+            working_map[goal[0]][goal[1]] = MapItem::Goal;
+            return Ok(working_map);
         }
 
-        return Ok(working_map);
+        return Err(MapGenerationError::PlacementConstraintsUnsatisfiable);
     }
 
-    pub fn list_unoccupied_positions(
-        &self,
-        map_to_check: [[MapItem; DIMY]; DIMX],
-    ) -> Vec<[usize; 2]> {
+    pub fn list_unoccupied_positions(&self, map_to_check: &MapGrid) -> Vec<[usize; 2]> {
         let mut unoccupied_positions: Vec<[usize; 2]> = Vec::new();
 
-        for rowidx in 0..DIMX {
-            for colidx in 0..DIMY {
+        for rowidx in 0..self.dimx {
+            for colidx in 0..self.dimy {
                 if map_to_check[rowidx][colidx] == MapItem::Empty {
                     unoccupied_positions.push([rowidx, colidx]);
                 }
@@ -273,12 +505,146 @@ impl<const DIMX: usize, const DIMY: usize> Map<DIMX, DIMY> {
         return unoccupied_positions;
     }
 
+    /// Fraction of the map's cells that are obstacles, measured on the current map.
+    pub fn obstacle_coverage(&self) -> Option<f32> {
+        let map = self.map.as_ref()?;
+        let obstacle_cells = map
+            .iter()
+            .flatten()
+            .filter(|item| **item == MapItem::Obstacle)
+            .count();
+        return Some(obstacle_cells as f32 / (self.dimx * self.dimy) as f32);
+    }
+
+    /// Find the first cell holding `item`, scanning in storage order.
+    pub fn find_item(&self, map_to_check: &MapGrid, item: MapItem) -> Option<[usize; 2]> {
+        for rowidx in 0..self.dimx {
+            for colidx in 0..self.dimy {
+                if map_to_check[rowidx][colidx] == item {
+                    return Some([rowidx, colidx]);
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Path distance from `start` to every cell, moving only through cells an entity could walk
+    /// on. Cells that can't be reached are None.
+    pub fn distance_field(
+        &self,
+        map_to_check: &MapGrid,
+        start: [usize; 2],
+    ) -> Vec<Vec<Option<u32>>> {
+        let mut distances: Vec<Vec<Option<u32>>> = vec![vec![None; self.dimy]; self.dimx];
+        let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+        distances[start[0]][start[1]] = Some(0);
+        frontier.push_back(start);
+        while let Some(position) = frontier.pop_front() {
+            let distance = distances[position[0]][position[1]].unwrap();
+            for [n_x, n_y] in self.neighbors_in_bounds(position) {
+                if distances[n_x][n_y].is_none() && map_to_check[n_x][n_y].is_traversable() {
+                    distances[n_x][n_y] = Some(distance + 1);
+                    frontier.push_back([n_x, n_y]);
+                }
+            }
+        }
+        return distances;
+    }
+
+    /// Length of the shortest 4-connected path between two cells, moving only through cells
+    /// an entity could walk on. Returns None when no such path exists.
+    pub fn path_length(
+        &self,
+        map_to_check: &MapGrid,
+        start: [usize; 2],
+        end: [usize; 2],
+    ) -> Option<u32> {
+        let mut distances: Vec<Vec<Option<u32>>> = vec![vec![None; self.dimy]; self.dimx];
+        let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+        distances[start[0]][start[1]] = Some(0);
+        frontier.push_back(start);
+        while let Some(position) = frontier.pop_front() {
+            let distance = distances[position[0]][position[1]].unwrap();
+            if position == end {
+                return Some(distance);
+            }
+            for [n_x, n_y] in self.neighbors_in_bounds(position) {
+                if distances[n_x][n_y].is_none() && map_to_check[n_x][n_y].is_traversable() {
+                    distances[n_x][n_y] = Some(distance + 1);
+                    frontier.push_back([n_x, n_y]);
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Check whether the hero on the given map has a path to the goal.
+    pub fn is_goal_reachable(&self, map_to_check: &MapGrid) -> bool {
+        let hero = self.find_item(map_to_check, MapItem::HeroEntity);
+        let goal = self.find_item(map_to_check, MapItem::Goal);
+        if let (Some(hero), Some(goal)) = (hero, goal) {
+            return self.path_length(map_to_check, hero, goal).is_some();
+        }
+        return false;
+    }
+
+    /// Turn the fewest possible obstacles into empty cells so that the hero can reach the goal.
+    /// Uses a 0-1 BFS where stepping onto an obstacle costs 1 and any other cell costs 0.
+    fn carve_path_to_goal(&self, working_map: &mut MapGrid) {
+        let hero = self.find_item(working_map, MapItem::HeroEntity);
+        let goal = self.find_item(working_map, MapItem::Goal);
+        let (Some(hero), Some(goal)) = (hero, goal) else {
+            return;
+        };
+        let mut cost: Vec<Vec<u32>> = vec![vec![u32::MAX; self.dimy]; self.dimx];
+        let mut came_from: Vec<Vec<Option<[usize; 2]>>> = vec![vec![None; self.dimy]; self.dimx];
+        let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+        cost[hero[0]][hero[1]] = 0;
+        frontier.push_back(hero);
+        while let Some(position) = frontier.pop_front() {
+            let current_cost = cost[position[0]][position[1]];
+            for [n_x, n_y] in self.neighbors_in_bounds(position) {
+                let step_cost = match working_map[n_x][n_y] {
+                    MapItem::Obstacle => 1,
+                    _ => 0,
+                };
+                if current_cost + step_cost < cost[n_x][n_y] {
+                    cost[n_x][n_y] = current_cost + step_cost;
+                    came_from[n_x][n_y] = Some(position);
+                    if step_cost == 0 {
+                        frontier.push_front([n_x, n_y]);
+                    } else {
+                        frontier.push_back([n_x, n_y]);
+                    }
+                }
+            }
+        }
+        let mut position = goal;
+        while let Some(previous) = came_from[position[0]][position[1]] {
+            if working_map[position[0]][position[1]] == MapItem::Obstacle {
+                working_map[position[0]][position[1]] = MapItem::Empty;
+            }
+            position = previous;
+        }
+    }
+
+    /// List the cells that share an edge with the given cell, wrapping around on a torus.
+    fn neighbors_in_bounds(&self, position: [usize; 2]) -> Vec<[usize; 2]> {
+        let mut neighbors: Vec<[usize; 2]> = Vec::new();
+        for delta in [[-1, 0], [0, -1], [0, 1], [1, 0]] {
+            if let Some(neighbor) = self.offset_position(position, delta) {
+                neighbors.push(neighbor);
+            }
+        }
+        return neighbors;
+    }
+
     /// Generate a string that contains the map converted to Unicode.
     pub fn generate_display_string(&self) -> Option<String> {
         let mut display_string = String::new();
-        if let Some(map) = self.map {
-            for colidx in 0..DIMY {
-                for rowidx in 0..DIMX {
+        if let Some(map) = &self.map {
+            for colidx in 0..self.dimy {
+                for rowidx in 0..self.dimx {
                     let map_item = map[rowidx][colidx];
                     let map_char = self.unicode_mappings.get(&map_item);
 
@@ -311,29 +677,24 @@ impl<const DIMX: usize, const DIMY: usize> Map<DIMX, DIMY> {
     pub fn get_empty_neighbors(&self, position: [usize; 2]) -> Vec<[usize; 2]> {
         let mut neighbors: Vec<[usize; 2]> = Vec::new();
 
-        let pos_x = position[0] as i32;
-        let pos_y = position[1] as i32;
-
-        for [delta_x, delta_y] in [[-1, 0], [0, -1], [0, 1], [1, 0]] {
-            let tx = pos_x + delta_x;
-            let ty = pos_y + delta_y;
-            if tx < DIMX as i32 && tx >= 0 && ty < DIMY as i32 && ty >= 0 {
+        for delta in [[-1, 0], [0, -1], [0, 1], [1, 0]] {
+            if let Some([tx, ty]) = self.offset_position(position, delta) {
                 match self
                     .map
-                    .expect("Map should exist when doing pathfinding calculations!")
-                    [tx as usize][ty as usize]
+                    .as_ref()
+                    .expect("Map should exist when doing pathfinding calculations!")[tx][ty]
                 {
                     MapItem::Empty => {
-                        neighbors.push([tx as usize, ty as usize]);
+                        neighbors.push([tx, ty]);
                     }
                     MapItem::Goal => {
-                        neighbors.push([tx as usize, ty as usize]);
+                        neighbors.push([tx, ty]);
                     }
                     MapItem::HeroEntity => {
-                        neighbors.push([tx as usize, ty as usize]);
+                        neighbors.push([tx, ty]);
                     }
                     MapItem::EnemyEntity => {
-                        neighbors.push([tx as usize, ty as usize]);
+                        neighbors.push([tx, ty]);
                     }
                     _ => {}
                 }
@@ -343,3 +704,229 @@ impl<const DIMX: usize, const DIMY: usize> Map<DIMX, DIMY> {
         return neighbors;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn generate(options: &MapGenerationOptions, seed: u64) -> (Map, MapGrid) {
+        let game_map = Map::new(32, 32);
+        let grid = game_map
+            .generate_map(options, &mut StdRng::seed_from_u64(seed))
+            .unwrap();
+        return (game_map, grid);
+    }
+
+    #[test]
+    fn retry_and_carve_always_connect_hero_and_goal() {
+        for connectivity in [
+            GoalConnectivity::Retry { max_attempts: 100 },
+            GoalConnectivity::Carve,
+        ] {
+            let options = MapGenerationOptions {
+                wall_coverage: 0.4,
+                coverage_tolerance: 0.02,
+                num_enemies: 5,
+                connectivity,
+                ..Default::default()
+            };
+            for seed in 0..10 {
+                let (game_map, grid) = generate(&options, seed);
+                assert!(
+                    game_map.is_goal_reachable(&grid),
+                    "{connectivity:?}, seed {seed}"
+                );
+            }
+        }
+    }
+
+    fn positions_of(grid: &MapGrid, item: MapItem) -> Vec<[usize; 2]> {
+        let mut positions: Vec<[usize; 2]> = Vec::new();
+        for (pos_x, column) in grid.iter().enumerate() {
+            for (pos_y, cell) in column.iter().enumerate() {
+                if *cell == item {
+                    positions.push([pos_x, pos_y]);
+                }
+            }
+        }
+        return positions;
+    }
+
+    fn with_placement(placement: PlacementOptions) -> MapGenerationOptions {
+        return MapGenerationOptions {
+            num_enemies: 10,
+            placement,
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn hero_distance_constraints_hold() {
+        let options = with_placement(PlacementOptions {
+            min_hero_enemy_distance: 15,
+            min_hero_goal_path_distance: 30,
+            ..Default::default()
+        });
+        for seed in 0..10 {
+            let (game_map, grid) = generate(&options, seed);
+            let hero = game_map.find_item(&grid, MapItem::HeroEntity).unwrap();
+            let goal = game_map.find_item(&grid, MapItem::Goal).unwrap();
+            assert!(game_map.path_length(&grid, hero, goal).unwrap() >= 30);
+            let enemies = positions_of(&grid, MapItem::EnemyEntity);
+            assert_eq!(enemies.len(), 10);
+            for [pos_x, pos_y] in enemies {
+                assert!(pos_x.abs_diff(hero[0]) + pos_y.abs_diff(hero[1]) >= 15);
+            }
+        }
+    }
+
+    #[test]
+    fn farthest_goal_is_the_most_distant_reachable_cell() {
+        let options = with_placement(PlacementOptions {
+            goal_placement: GoalPlacement::Farthest,
+            ..Default::default()
+        });
+        for seed in 0..10 {
+            let (game_map, grid) = generate(&options, seed);
+            let hero = game_map.find_item(&grid, MapItem::HeroEntity).unwrap();
+            let goal = game_map.find_item(&grid, MapItem::Goal).unwrap();
+            // Enemies are placed after the goal, so measure as if they weren't there.
+            let mut without_enemies = grid.clone();
+            for [pos_x, pos_y] in positions_of(&grid, MapItem::EnemyEntity) {
+                without_enemies[pos_x][pos_y] = MapItem::Empty;
+            }
+            let distances = game_map.distance_field(&without_enemies, hero);
+            let farthest = distances.iter().flatten().flatten().max().copied();
+            assert_eq!(distances[goal[0]][goal[1]], farthest, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn spread_enemies_keep_their_spacing() {
+        let options = with_placement(PlacementOptions {
+            enemy_placement: EnemyPlacement::Spread { min_spacing: 6. },
+            ..Default::default()
+        });
+        for seed in 0..10 {
+            let (_, grid) = generate(&options, seed);
+            let enemies = positions_of(&grid, MapItem::EnemyEntity);
+            assert_eq!(enemies.len(), 10);
+            for (idx, first) in enemies.iter().enumerate() {
+                for second in &enemies[idx + 1..] {
+                    let delta_x = first[0] as f32 - second[0] as f32;
+                    let delta_y = first[1] as f32 - second[1] as f32;
+                    assert!(f32::hypot(delta_x, delta_y) >= 6., "seed {seed}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn guards_stay_near_the_goal() {
+        let options = with_placement(PlacementOptions {
+            enemy_placement: EnemyPlacement::GuardGoal { radius: 6 },
+            ..Default::default()
+        });
+        for seed in 0..10 {
+            let (game_map, grid) = generate(&options, seed);
+            let goal = game_map.find_item(&grid, MapItem::Goal).unwrap();
+            let enemies = positions_of(&grid, MapItem::EnemyEntity);
+            assert_eq!(enemies.len(), 10);
+            for enemy in enemies {
+                let distance = game_map.path_length(&grid, goal, enemy).unwrap();
+                assert!(distance <= 6, "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_placements_are_reported() {
+        let cases = [
+            PlacementOptions {
+                min_hero_goal_path_distance: 10_000,
+                ..Default::default()
+            },
+            PlacementOptions {
+                enemy_placement: EnemyPlacement::Spread { min_spacing: 100. },
+                ..Default::default()
+            },
+            PlacementOptions {
+                enemy_placement: EnemyPlacement::GuardGoal { radius: 0 },
+                ..Default::default()
+            },
+        ];
+        for placement in cases {
+            let options = MapGenerationOptions {
+                connectivity: GoalConnectivity::Retry { max_attempts: 3 },
+                ..with_placement(placement)
+            };
+            let result = Map::new(32, 32).generate_map(&options, &mut StdRng::seed_from_u64(0));
+            assert!(
+                matches!(
+                    result,
+                    Err(MapGenerationError::PlacementConstraintsUnsatisfiable)
+                ),
+                "{placement:?}"
+            );
+        }
+
+        let too_far = with_placement(PlacementOptions {
+            min_hero_enemy_distance: 1_000,
+            ..Default::default()
+        });
+        let result = Map::new(32, 32).generate_map(&too_far, &mut StdRng::seed_from_u64(0));
+        assert!(matches!(result, Err(MapGenerationError::RanOutOfSpace)));
+    }
+
+    #[test]
+    fn torus_neighbors_and_displacement_wrap() {
+        let bounded = Map::new(5, 4);
+        let mut torus = Map::new(5, 4);
+        torus.set_topology(MapTopology::Torus);
+
+        assert_eq!(bounded.neighbors_in_bounds([0, 0]), vec![[0, 1], [1, 0]]);
+        assert_eq!(
+            torus.neighbors_in_bounds([0, 0]),
+            vec![[4, 0], [0, 3], [0, 1], [1, 0]]
+        );
+        assert_eq!(bounded.offset_position([4, 3], [1, 1]), None);
+        assert_eq!(torus.offset_position([4, 3], [1, 1]), Some([0, 0]));
+
+        assert_eq!(bounded.displacement([0, 0], [4, 3]), [4, 3]);
+        assert_eq!(torus.displacement([0, 0], [4, 3]), [-1, -1]);
+        assert_eq!(torus.displacement([4, 3], [0, 0]), [1, 1]);
+        // Halfway round is reached without wrapping.
+        assert_eq!(torus.displacement([1, 1], [3, 3]), [2, 2]);
+    }
+
+    #[test]
+    fn torus_generation_measures_distances_around_the_edges() {
+        let options = MapGenerationOptions {
+            wall_coverage: 0.4,
+            coverage_tolerance: 0.02,
+            connectivity: GoalConnectivity::Carve,
+            placement: PlacementOptions {
+                min_hero_enemy_distance: 12,
+                ..Default::default()
+            },
+            topology: MapTopology::Torus,
+            ..Default::default()
+        };
+        let mut torus = Map::new(32, 32);
+        torus.set_topology(MapTopology::Torus);
+        for seed in 0..10 {
+            let (_, grid) = generate(&options, seed);
+            assert!(torus.is_goal_reachable(&grid), "seed {seed}");
+            let hero = torus.find_item(&grid, MapItem::HeroEntity).unwrap();
+            for pos_x in 0..32 {
+                for pos_y in 0..32 {
+                    if grid[pos_x][pos_y] == MapItem::EnemyEntity {
+                        let [delta_x, delta_y] = torus.displacement(hero, [pos_x, pos_y]);
+                        assert!(delta_x.abs() + delta_y.abs() >= 12, "seed {seed}");
+                    }
+                }
+            }
+        }
+    }
+}
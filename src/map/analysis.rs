@@ -0,0 +1,261 @@
+// Difficulty metrics for finished maps
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use crate::map::{Map, MapGrid, MapItem};
+
+/// How far (in steps along open cells) from the optimal path an enemy still counts as near it.
+pub const NEAR_PATH_RADIUS: u32 = 3;
+
+/// Measurements for bucketing maps by difficulty. Open cells are the ones an entity can walk
+/// on (see `MapItem::is_traversable`).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DifficultyReport {
+    /// Length of the shortest hero-to-goal path, or None if the goal can't be reached.
+    pub path_length: Option<u32>,
+    /// Euclidean distance between the hero and the goal, in cells (the short way around on a
+    /// torus).
+    pub straight_line_distance: f32,
+    /// `path_length` over `straight_line_distance`; 1 means the way is open.
+    pub path_to_straight_line_ratio: Option<f32>,
+    /// Open cells whose removal would split their region in two (articulation points).
+    pub chokepoints: usize,
+    /// Number of cells in the largest 4-connected open region.
+    pub largest_region_size: usize,
+    /// Enemies within `NEAR_PATH_RADIUS` steps of the shortest path.
+    pub enemies_near_path: usize,
+    /// `enemies_near_path` divided by the number of open cells within that radius.
+    pub enemy_density_near_path: f32,
+    /// Open cells the hero has no path to.
+    pub unreachable_cells: usize,
+}
+
+impl Map {
+    /// Analyse the map. Returns None if the map hasn't been generated or lacks a hero or goal.
+    pub fn difficulty_report(&self) -> Option<DifficultyReport> {
+        let map = self.map.as_ref()?;
+        let hero = self.find_item(map, MapItem::HeroEntity)?;
+        let goal = self.find_item(map, MapItem::Goal)?;
+
+        let [delta_x, delta_y] = self.displacement(hero, goal);
+        let straight_line_distance = f32::hypot(delta_x as f32, delta_y as f32);
+        let goal_distances = self.distance_field(map, goal);
+        let path_length = goal_distances[hero[0]][hero[1]];
+        let path_to_straight_line_ratio =
+            path_length.map(|length| length as f32 / straight_line_distance);
+
+        let hero_distances = self.distance_field(map, hero);
+        let open_cells = map
+            .iter()
+            .flatten()
+            .filter(|item| item.is_traversable())
+            .count();
+        let reachable_cells = hero_distances
+            .iter()
+            .flatten()
+            .filter(|d| d.is_some())
+            .count();
+
+        let (enemies_near_path, enemy_density_near_path) = match path_length {
+            Some(_) => self.enemies_near_path(map, hero, &goal_distances),
+            None => (0, 0.),
+        };
+
+        return Some(DifficultyReport {
+            path_length,
+            straight_line_distance,
+            path_to_straight_line_ratio,
+            chokepoints: self.count_chokepoints(map),
+            largest_region_size: self.largest_open_region(map),
+            enemies_near_path,
+            enemy_density_near_path,
+            unreachable_cells: open_cells - reachable_cells,
+        });
+    }
+
+    /// Follow one shortest path from `hero` down the goal's distance field, then count the
+    /// enemies within `NEAR_PATH_RADIUS` steps of it.
+    fn enemies_near_path(
+        &self,
+        map: &MapGrid,
+        hero: [usize; 2],
+        goal_distances: &[Vec<Option<u32>>],
+    ) -> (usize, f32) {
+        let mut near_distance: Vec<Vec<Option<u32>>> = vec![vec![None; self.dimy]; self.dimx];
+        let mut frontier: VecDeque<[usize; 2]> = VecDeque::new();
+        let mut position = hero;
+        loop {
+            near_distance[position[0]][position[1]] = Some(0);
+            frontier.push_back(position);
+            let distance = goal_distances[position[0]][position[1]].unwrap();
+            if distance == 0 {
+                break;
+            }
+            position = self
+                .neighbors_in_bounds(position)
+                .into_iter()
+                .find(|[n_x, n_y]| goal_distances[*n_x][*n_y] == Some(distance - 1))
+                .expect("Every cell on a shortest path has a closer neighbor");
+        }
+
+        let mut near_cells = 0;
+        let mut near_enemies = 0;
+        while let Some([pos_x, pos_y]) = frontier.pop_front() {
+            near_cells += 1;
+            if map[pos_x][pos_y] == MapItem::EnemyEntity {
+                near_enemies += 1;
+            }
+            let distance = near_distance[pos_x][pos_y].unwrap();
+            if distance == NEAR_PATH_RADIUS {
+                continue;
+            }
+            for [n_x, n_y] in self.neighbors_in_bounds([pos_x, pos_y]) {
+                if near_distance[n_x][n_y].is_none() && map[n_x][n_y].is_traversable() {
+                    near_distance[n_x][n_y] = Some(distance + 1);
+                    frontier.push_back([n_x, n_y]);
+                }
+            }
+        }
+        return (near_enemies, near_enemies as f32 / near_cells as f32);
+    }
+
+    fn largest_open_region(&self, map: &MapGrid) -> usize {
+        let mut visited: Vec<Vec<bool>> = vec![vec![false; self.dimy]; self.dimx];
+        let mut largest = 0;
+        for start_x in 0..self.dimx {
+            for start_y in 0..self.dimy {
+                if visited[start_x][start_y] || !map[start_x][start_y].is_traversable() {
+                    continue;
+                }
+                visited[start_x][start_y] = true;
+                let mut frontier: Vec<[usize; 2]> = vec![[start_x, start_y]];
+                let mut size = 0;
+                while let Some(position) = frontier.pop() {
+                    size += 1;
+                    for [n_x, n_y] in self.neighbors_in_bounds(position) {
+                        if !visited[n_x][n_y] && map[n_x][n_y].is_traversable() {
+                            visited[n_x][n_y] = true;
+                            frontier.push([n_x, n_y]);
+                        }
+                    }
+                }
+                largest = largest.max(size);
+            }
+        }
+        return largest;
+    }
+
+    /// Count articulation points of the open-cell graph with Tarjan's algorithm. The depth
+    /// first search keeps its own stack, since a recursive one would overflow on large maps.
+    fn count_chokepoints(&self, map: &MapGrid) -> usize {
+        const UNVISITED: u32 = u32::MAX;
+        let mut discovered: Vec<Vec<u32>> = vec![vec![UNVISITED; self.dimy]; self.dimx];
+        let mut low: Vec<Vec<u32>> = vec![vec![UNVISITED; self.dimy]; self.dimx];
+        let mut is_chokepoint: Vec<Vec<bool>> = vec![vec![false; self.dimy]; self.dimx];
+        let mut time = 0;
+        for root_x in 0..self.dimx {
+            for root_y in 0..self.dimy {
+                let root = [root_x, root_y];
+                if discovered[root_x][root_y] != UNVISITED || !map[root_x][root_y].is_traversable()
+                {
+                    continue;
+                }
+                discovered[root_x][root_y] = time;
+                low[root_x][root_y] = time;
+                time += 1;
+                let mut root_children = 0;
+                // (cell, its DFS parent, neighbors still to visit)
+                let mut stack: Vec<([usize; 2], Option<[usize; 2]>, Vec<[usize; 2]>)> =
+                    vec![(root, None, self.neighbors_in_bounds(root))];
+                while let Some((cell, parent, remaining)) = stack.last_mut() {
+                    let [c_x, c_y] = *cell;
+                    if let Some([n_x, n_y]) = remaining.pop() {
+                        if !map[n_x][n_y].is_traversable() {
+                            continue;
+                        }
+                        if discovered[n_x][n_y] == UNVISITED {
+                            discovered[n_x][n_y] = time;
+                            low[n_x][n_y] = time;
+                            time += 1;
+                            if *cell == root {
+                                root_children += 1;
+                            }
+                            let child = [n_x, n_y];
+                            stack.push((child, Some([c_x, c_y]), self.neighbors_in_bounds(child)));
+                        } else if Some([n_x, n_y]) != *parent {
+                            low[c_x][c_y] = low[c_x][c_y].min(discovered[n_x][n_y]);
+                        }
+                        continue;
+                    }
+                    let parent = *parent;
+                    stack.pop();
+                    if let Some([p_x, p_y]) = parent {
+                        low[p_x][p_y] = low[p_x][p_y].min(low[c_x][c_y]);
+                        if [p_x, p_y] != root && low[c_x][c_y] >= discovered[p_x][p_y] {
+                            is_chokepoint[p_x][p_y] = true;
+                        }
+                    }
+                }
+                if root_children > 1 {
+                    is_chokepoint[root_x][root_y] = true;
+                }
+            }
+        }
+        return is_chokepoint.iter().flatten().filter(|cell| **cell).count();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(level: &str) -> DifficultyReport {
+        return Map::from_text(level).unwrap().difficulty_report().unwrap();
+    }
+
+    #[test]
+    fn corridor_cells_between_the_ends_are_chokepoints() {
+        let corridor = report("H...G");
+        assert_eq!(corridor.path_length, Some(4));
+        assert_eq!(corridor.straight_line_distance, 4.);
+        assert_eq!(corridor.path_to_straight_line_ratio, Some(1.));
+        assert_eq!(corridor.chokepoints, 3);
+        assert_eq!(corridor.largest_region_size, 5);
+        assert_eq!(corridor.unreachable_cells, 0);
+    }
+
+    #[test]
+    fn bridge_between_rooms_and_its_ends_are_chokepoints() {
+        let bridge = report("..#..\nH....\n..#.G");
+        assert_eq!(bridge.path_length, Some(5));
+        assert_eq!(bridge.straight_line_distance, 17f32.sqrt());
+        assert_eq!(bridge.path_to_straight_line_ratio, Some(5. / 17f32.sqrt()));
+        assert_eq!(bridge.chokepoints, 3);
+        assert_eq!(bridge.largest_region_size, 13);
+    }
+
+    #[test]
+    fn walled_off_pockets_are_unreachable() {
+        let pocket = report("H..#.\n...#.\n..G#.");
+        assert_eq!(pocket.largest_region_size, 9);
+        assert_eq!(pocket.unreachable_cells, 3);
+
+        let blocked = report("H#G");
+        assert_eq!(blocked.path_length, None);
+        assert_eq!(blocked.path_to_straight_line_ratio, None);
+        assert_eq!(blocked.enemies_near_path, 0);
+        assert_eq!(blocked.unreachable_cells, 1);
+    }
+
+    #[test]
+    fn only_enemies_within_the_radius_of_the_path_are_near_it() {
+        let open = report("H.....G\n.......\n.......\nE......\n......E");
+        assert_eq!(open.path_length, Some(6));
+        assert_eq!(open.chokepoints, 0);
+        assert_eq!(open.enemies_near_path, 1);
+        // The path's row and the three below it.
+        assert_eq!(open.enemy_density_near_path, 1. / 28.);
+        assert_eq!(open.unreachable_cells, 0);
+    }
+}
@@ -0,0 +1,194 @@
+// Cellular-automata cave generator
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+
+use crate::map::{Map, MapGenerationError, MapGenerationOptions, MapGenerator, MapGrid, MapItem};
+
+/// Settings for the cave obstacle generator.
+///
+/// The map starts as random noise where each cell is an obstacle with probability `fill`.
+/// Each of the `iterations` smoothing passes counts the obstacles among a cell's eight
+/// neighbours: an empty cell becomes an obstacle when the count is at least `birth_threshold`,
+/// and an obstacle stays one when the count is at least `survival_threshold`. Cells past the
+/// edge of a bounded map count as obstacles; on a torus the neighbourhood wraps. Afterwards only
+/// the largest connected open region is kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CaveGenerator {
+    pub fill: f32,
+    pub iterations: u32,
+    pub birth_threshold: u8,
+    pub survival_threshold: u8,
+}
+
+impl Default for CaveGenerator {
+    fn default() -> CaveGenerator {
+        CaveGenerator {
+            fill: 0.45,
+            iterations: 5,
+            birth_threshold: 5,
+            survival_threshold: 4,
+        }
+    }
+}
+
+impl MapGenerator for CaveGenerator {
+    /// Build the obstacle layout for a cave the size of `map`.
+    fn generate(
+        &self,
+        map: &Map,
+        _options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if self.fill.clamp(0., 1.) != self.fill
+            || self.birth_threshold > 8
+            || self.survival_threshold > 8
+        {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+
+        let mut working_map = map.filled_grid(MapItem::Empty);
+        for pos_x in 0..map.dimx() {
+            for pos_y in 0..map.dimy() {
+                if rng.random_bool(self.fill as f64) {
+                    working_map[pos_x][pos_y] = MapItem::Obstacle;
+                }
+            }
+        }
+
+        for _ in 0..self.iterations {
+            working_map = self.smooth(map, &working_map);
+        }
+
+        if !keep_largest_open_region(map, &mut working_map) {
+            return Err(MapGenerationError::RanOutOfSpace);
+        }
+        return Ok(working_map);
+    }
+}
+
+impl CaveGenerator {
+    /// Apply the birth/survival rules to every cell at once.
+    fn smooth(&self, map: &Map, working_map: &MapGrid) -> MapGrid {
+        let mut next_map = working_map.clone();
+        for pos_x in 0..working_map.len() {
+            for pos_y in 0..working_map[pos_x].len() {
+                let walls = count_wall_neighbors(map, working_map, [pos_x, pos_y]);
+                let is_wall = working_map[pos_x][pos_y] == MapItem::Obstacle;
+                let becomes_wall = if is_wall {
+                    walls >= self.survival_threshold
+                } else {
+                    walls >= self.birth_threshold
+                };
+                next_map[pos_x][pos_y] = if becomes_wall {
+                    MapItem::Obstacle
+                } else {
+                    MapItem::Empty
+                };
+            }
+        }
+        return next_map;
+    }
+}
+
+/// Count obstacles in the Moore neighbourhood of a cell, treating cells off a bounded map as
+/// walls. On a torus the neighbourhood wraps around the edges.
+fn count_wall_neighbors(map: &Map, working_map: &MapGrid, position: [usize; 2]) -> u8 {
+    let mut walls = 0;
+    for delta_x in -1..=1 {
+        for delta_y in -1..=1 {
+            if delta_x == 0 && delta_y == 0 {
+                continue;
+            }
+            let is_wall = match map.offset_position(position, [delta_x, delta_y]) {
+                Some([tx, ty]) => working_map[tx][ty] == MapItem::Obstacle,
+                None => true,
+            };
+            if is_wall {
+                walls += 1;
+            }
+        }
+    }
+    return walls;
+}
+
+/// Fill every open region except the largest one with obstacles. Regions join across the
+/// edges of a torus. Returns false when the map has no open cells at all.
+fn keep_largest_open_region(map: &Map, working_map: &mut MapGrid) -> bool {
+    let [dimx, dimy] = [working_map.len(), working_map[0].len()];
+    let mut region_ids: Vec<Vec<Option<usize>>> = vec![vec![None; dimy]; dimx];
+    let mut region_sizes: Vec<usize> = Vec::new();
+
+    for start_x in 0..dimx {
+        for start_y in 0..dimy {
+            if working_map[start_x][start_y] != MapItem::Empty
+                || region_ids[start_x][start_y].is_some()
+            {
+                continue;
+            }
+            let region_id = region_sizes.len();
+            let mut size = 0;
+            let mut frontier: VecDeque<[usize; 2]> = VecDeque::from([[start_x, start_y]]);
+            region_ids[start_x][start_y] = Some(region_id);
+            while let Some(position) = frontier.pop_front() {
+                size += 1;
+                for delta in [[-1, 0], [0, -1], [0, 1], [1, 0]] {
+                    let Some([tx, ty]) = map.offset_position(position, delta) else {
+                        continue;
+                    };
+                    if working_map[tx][ty] == MapItem::Empty && region_ids[tx][ty].is_none() {
+                        region_ids[tx][ty] = Some(region_id);
+                        frontier.push_back([tx, ty]);
+                    }
+                }
+            }
+            region_sizes.push(size);
+        }
+    }
+
+    let Some(largest_region) = (0..region_sizes.len()).max_by_key(|id| region_sizes[*id]) else {
+        return false;
+    };
+    for pos_x in 0..dimx {
+        for pos_y in 0..dimy {
+            if region_ids[pos_x][pos_y].is_some_and(|id| id != largest_region) {
+                working_map[pos_x][pos_y] = MapItem::Obstacle;
+            }
+        }
+    }
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapTopology;
+    use rand::SeedableRng;
+
+    #[test]
+    fn cave_keeps_exactly_one_open_region() {
+        let mut torus = Map::new(48, 32);
+        torus.set_topology(MapTopology::Torus);
+        for (map, seed) in [Map::new(48, 32), torus]
+            .iter()
+            .flat_map(|map| (0..10).map(move |seed| (map, seed)))
+        {
+            let working_map = CaveGenerator::default()
+                .generate(
+                    map,
+                    &MapGenerationOptions::default(),
+                    &mut StdRng::seed_from_u64(seed),
+                )
+                .unwrap();
+            let start = map.find_item(&working_map, MapItem::Empty).unwrap();
+            let distances = map.distance_field(&working_map, start);
+            for pos_x in 0..map.dimx() {
+                for pos_y in 0..map.dimy() {
+                    let is_open = working_map[pos_x][pos_y] == MapItem::Empty;
+                    assert_eq!(is_open, distances[pos_x][pos_y].is_some(), "seed {seed}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// Rooms-and-corridors dungeon generator
+
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::map::{Map, MapGenerationError, MapGenerationOptions, MapGenerator, MapGrid, MapItem};
+
+/// How rooms are laid out before they get joined by corridors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoomPlacement {
+    /// Recursively split the map in two (binary space partitioning), put one room in each
+    /// leaf and join sibling subtrees. Rooms end up evenly spread over the map.
+    Bsp,
+    /// Drop up to `max_rooms` non-overlapping rooms at random and join them in a chain.
+    Random,
+}
+
+impl RoomPlacement {
+    /// Look up a placement strategy by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<RoomPlacement> {
+        match name {
+            "bsp" => {
+                return Some(RoomPlacement::Bsp);
+            }
+            "random" => {
+                return Some(RoomPlacement::Random);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// Settings for the dungeon obstacle generator.
+///
+/// Room sizes are edge lengths in cells, measured without the surrounding walls.
+/// `max_rooms` only applies to `RoomPlacement::Random`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DungeonGenerator {
+    pub placement: RoomPlacement,
+    pub min_room_size: usize,
+    pub max_room_size: usize,
+    pub corridor_width: usize,
+    pub max_rooms: usize,
+}
+
+impl Default for DungeonGenerator {
+    fn default() -> DungeonGenerator {
+        DungeonGenerator {
+            placement: RoomPlacement::Bsp,
+            min_room_size: 4,
+            max_room_size: 12,
+            corridor_width: 1,
+            max_rooms: 12,
+        }
+    }
+}
+
+/// An axis-aligned rectangle of cells.
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn center(&self) -> [usize; 2] {
+        return [self.x + self.width / 2, self.y + self.height / 2];
+    }
+
+    /// Whether the two rectangles overlap once this one is grown by `padding` on every side.
+    fn overlaps(&self, other: &Rect, padding: usize) -> bool {
+        return self.x < other.x + other.width + padding
+            && other.x < self.x + self.width + padding
+            && self.y < other.y + other.height + padding
+            && other.y < self.y + self.height + padding;
+    }
+}
+
+impl MapGenerator for DungeonGenerator {
+    /// Build the obstacle layout for a dungeon the size of `map`.
+    fn generate(
+        &self,
+        map: &Map,
+        _options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if self.min_room_size == 0
+            || self.min_room_size > self.max_room_size
+            || self.corridor_width == 0
+        {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+        // Every room needs a wall on each side, so the map has to fit at least one of them.
+        if map.dimx() < self.min_room_size + 2 || map.dimy() < self.min_room_size + 2 {
+            return Err(MapGenerationError::RanOutOfSpace);
+        }
+
+        let mut working_map = map.filled_grid(MapItem::Obstacle);
+        let whole_map = Rect {
+            x: 0,
+            y: 0,
+            width: map.dimx(),
+            height: map.dimy(),
+        };
+        let rooms = match self.placement {
+            RoomPlacement::Bsp => self.place_bsp(&mut working_map, whole_map, rng),
+            RoomPlacement::Random => self.place_random(&mut working_map, whole_map, rng),
+        };
+        if rooms.is_empty() {
+            return Err(MapGenerationError::RanOutOfSpace);
+        }
+        return Ok(working_map);
+    }
+}
+
+impl DungeonGenerator {
+    /// Split `area` until it is too small to hold two rooms, then carve one room per leaf.
+    /// Returns the rooms carved inside `area`, after joining the two halves with a corridor.
+    fn place_bsp(&self, working_map: &mut MapGrid, area: Rect, rng: &mut StdRng) -> Vec<Rect> {
+        let min_leaf = self.min_room_size + 2;
+        let can_split_x = area.width >= min_leaf * 2;
+        let can_split_y = area.height >= min_leaf * 2;
+        let needs_split =
+            area.width > self.max_room_size + 2 || area.height > self.max_room_size + 2;
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => None,
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (true, true) => Some(area.width > area.height || rng.random_bool(0.5)),
+        };
+        let Some(split_x) = split_x.filter(|_| needs_split || rng.random_bool(0.5)) else {
+            return self
+                .carve_room_in(working_map, area, rng)
+                .into_iter()
+                .collect();
+        };
+
+        let (first, second) = if split_x {
+            let split = rng.random_range(min_leaf..=area.width - min_leaf);
+            (
+                Rect {
+                    width: split,
+                    ..area
+                },
+                Rect {
+                    x: area.x + split,
+                    width: area.width - split,
+                    ..area
+                },
+            )
+        } else {
+            let split = rng.random_range(min_leaf..=area.height - min_leaf);
+            (
+                Rect {
+                    height: split,
+                    ..area
+                },
+                Rect {
+                    y: area.y + split,
+                    height: area.height - split,
+                    ..area
+                },
+            )
+        };
+        let mut rooms = self.place_bsp(working_map, first, rng);
+        let second_rooms = self.place_bsp(working_map, second, rng);
+        if !rooms.is_empty() && !second_rooms.is_empty() {
+            let from = rooms[rng.random_range(0..rooms.len())].center();
+            let to = second_rooms[rng.random_range(0..second_rooms.len())].center();
+            self.carve_corridor(working_map, from, to, rng);
+        }
+        rooms.extend(second_rooms);
+        return rooms;
+    }
+
+    /// Carve a randomly sized room inside `area`, leaving at least one wall cell around it.
+    fn carve_room_in(
+        &self,
+        working_map: &mut MapGrid,
+        area: Rect,
+        rng: &mut StdRng,
+    ) -> Option<Rect> {
+        if area.width < self.min_room_size + 2 || area.height < self.min_room_size + 2 {
+            return None;
+        }
+        let width =
+            rng.random_range(self.min_room_size..=usize::min(self.max_room_size, area.width - 2));
+        let height =
+            rng.random_range(self.min_room_size..=usize::min(self.max_room_size, area.height - 2));
+        let room = Rect {
+            x: area.x + rng.random_range(1..=area.width - width - 1),
+            y: area.y + rng.random_range(1..=area.height - height - 1),
+            width,
+            height,
+        };
+        carve_rect(working_map, room);
+        return Some(room);
+    }
+
+    /// Try a fixed number of random spots for rooms and chain every accepted room to the
+    /// previous one.
+    fn place_random(&self, working_map: &mut MapGrid, area: Rect, rng: &mut StdRng) -> Vec<Rect> {
+        let mut rooms: Vec<Rect> = Vec::new();
+        for _ in 0..self.max_rooms * 10 {
+            if rooms.len() >= self.max_rooms {
+                break;
+            }
+            let width = rng
+                .random_range(self.min_room_size..=usize::min(self.max_room_size, area.width - 2));
+            let height = rng
+                .random_range(self.min_room_size..=usize::min(self.max_room_size, area.height - 2));
+            let room = Rect {
+                x: rng.random_range(1..=area.width - width - 1),
+                y: rng.random_range(1..=area.height - height - 1),
+                width,
+                height,
+            };
+            if rooms.iter().any(|other| room.overlaps(other, 1)) {
+                continue;
+            }
+            carve_rect(working_map, room);
+            if let Some(previous) = rooms.last() {
+                self.carve_corridor(working_map, previous.center(), room.center(), rng);
+            }
+            rooms.push(room);
+        }
+        return rooms;
+    }
+
+    /// Join two cells with an L-shaped corridor `corridor_width` cells wide.
+    fn carve_corridor(
+        &self,
+        working_map: &mut MapGrid,
+        from: [usize; 2],
+        to: [usize; 2],
+        rng: &mut StdRng,
+    ) {
+        let corner = if rng.random_bool(0.5) {
+            [to[0], from[1]]
+        } else {
+            [from[0], to[1]]
+        };
+        for [start, end] in [[from, corner], [corner, to]] {
+            let segment = Rect {
+                x: usize::min(start[0], end[0]),
+                y: usize::min(start[1], end[1]),
+                width: start[0].abs_diff(end[0]) + self.corridor_width,
+                height: start[1].abs_diff(end[1]) + self.corridor_width,
+            };
+            carve_rect(working_map, segment);
+        }
+    }
+}
+
+/// Empty every cell of `rect` that lies on the map.
+fn carve_rect(working_map: &mut MapGrid, rect: Rect) {
+    for pos_x in rect.x..usize::min(rect.x + rect.width, working_map.len()) {
+        for pos_y in rect.y..usize::min(rect.y + rect.height, working_map[pos_x].len()) {
+            working_map[pos_x][pos_y] = MapItem::Empty;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Whether every open cell can reach every other one.
+    fn is_connected(map: &Map, working_map: &MapGrid) -> bool {
+        let Some(start) = map.find_item(working_map, MapItem::Empty) else {
+            return false;
+        };
+        let distances = map.distance_field(working_map, start);
+        for pos_x in 0..map.dimx() {
+            for pos_y in 0..map.dimy() {
+                if working_map[pos_x][pos_y] == MapItem::Empty && distances[pos_x][pos_y].is_none()
+                {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    #[test]
+    fn dungeon_rooms_are_all_connected() {
+        let map = Map::new(64, 48);
+        for placement in [RoomPlacement::Bsp, RoomPlacement::Random] {
+            let generator = DungeonGenerator {
+                placement,
+                ..Default::default()
+            };
+            for seed in 0..10 {
+                let working_map = generator
+                    .generate(
+                        &map,
+                        &MapGenerationOptions::default(),
+                        &mut StdRng::seed_from_u64(seed),
+                    )
+                    .unwrap();
+                assert!(
+                    is_connected(&map, &working_map),
+                    "{placement:?}, seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smallest_maps_and_rooms_do_not_panic() {
+        for placement in [RoomPlacement::Bsp, RoomPlacement::Random] {
+            for min_room_size in 1..=4 {
+                for max_room_size in [min_room_size, min_room_size + 3] {
+                    for extra in 0..4 {
+                        let map = Map::new(min_room_size + 2 + extra, min_room_size + 2);
+                        let generator = DungeonGenerator {
+                            placement,
+                            min_room_size,
+                            max_room_size,
+                            corridor_width: 1 + extra,
+                            max_rooms: 4,
+                        };
+                        let working_map = generator
+                            .generate(
+                                &map,
+                                &MapGenerationOptions::default(),
+                                &mut StdRng::seed_from_u64(0),
+                            )
+                            .unwrap();
+                        assert!(is_connected(&map, &working_map));
+                    }
+                }
+            }
+        }
+        let map = Map::new(3, 3);
+        let generator = DungeonGenerator {
+            min_room_size: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generator.generate(
+                &map,
+                &MapGenerationOptions::default(),
+                &mut StdRng::seed_from_u64(0)
+            ),
+            Err(MapGenerationError::RanOutOfSpace)
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+// Checked map editing with undo
+
+use crate::map::{Map, MapGrid, MapItem, MapParseError};
+
+#[derive(Debug, PartialEq)]
+pub enum MapEditError {
+    /// The position is outside the map.
+    OutOfBounds { position: [usize; 2] },
+    /// The target cell already holds something that the edit would overwrite.
+    Occupied { position: [usize; 2], item: MapItem },
+    /// There is no hero, goal or enemy at the position.
+    NoEntity { position: [usize; 2] },
+    /// The map already has a hero or goal, and may only have one.
+    AlreadyPlaced { item: MapItem },
+    /// Terrain edits only accept `Empty`, `Obstacle` and `Junk`; entity placement only accepts
+    /// `HeroEntity`, `Goal` and `EnemyEntity`.
+    WrongKind { item: MapItem },
+    /// There is no edit left to undo.
+    NothingToUndo,
+}
+
+/// Edits a map while keeping it playable: positions are bounds checked, entities never share
+/// a cell with an obstacle or each other, and there is at most one hero and one goal.
+/// `finish` then checks there is exactly one of each.
+///
+/// Every edit can be undone. Edits made inside `batch` are undone together, and a batch that
+/// fails part way leaves the map as it was.
+pub struct MapEditor {
+    map: Map,
+    /// One entry per undoable step, holding every cell it changed and what was there before.
+    undo_stack: Vec<Vec<([usize; 2], MapItem)>>,
+    in_batch: bool,
+}
+
+impl MapEditor {
+    /// Start editing `map`. A map without a grid is edited as an all-empty one.
+    pub fn new(mut map: Map) -> MapEditor {
+        if map.map.is_none() {
+            map.map = Some(map.filled_grid(MapItem::Empty));
+        }
+        return MapEditor {
+            map,
+            undo_stack: Vec::new(),
+            in_batch: false,
+        };
+    }
+
+    /// Start editing an empty `dimx` by `dimy` map.
+    pub fn empty(dimx: usize, dimy: usize) -> MapEditor {
+        return MapEditor::new(Map::new(dimx, dimy));
+    }
+
+    /// The map as edited so far.
+    pub fn map(&self) -> &Map {
+        return &self.map;
+    }
+
+    /// What is at `position`.
+    pub fn get(&self, position: [usize; 2]) -> Result<MapItem, MapEditError> {
+        self.check_bounds(position)?;
+        return Ok(self.grid()[position[0]][position[1]]);
+    }
+
+    /// Stop editing and return the map, checking it has exactly one hero and one goal.
+    pub fn finish(self) -> Result<Map, MapParseError> {
+        self.map.validate_level()?;
+        return Ok(self.map);
+    }
+
+    /// Turn the cell at `position` into `terrain` (`Empty`, `Obstacle` or `Junk`). Fails if
+    /// an entity or the goal is there.
+    pub fn set_terrain(
+        &mut self,
+        position: [usize; 2],
+        terrain: MapItem,
+    ) -> Result<(), MapEditError> {
+        if !matches!(terrain, MapItem::Empty | MapItem::Obstacle | MapItem::Junk) {
+            return Err(MapEditError::WrongKind { item: terrain });
+        }
+        let current = self.get(position)?;
+        if is_entity(current) {
+            return Err(MapEditError::Occupied {
+                position,
+                item: current,
+            });
+        }
+        return self.step(|editor| editor.write(position, terrain));
+    }
+
+    /// Make the cell at `position` empty terrain. Fails if an entity or the goal is there.
+    pub fn clear_terrain(&mut self, position: [usize; 2]) -> Result<(), MapEditError> {
+        return self.set_terrain(position, MapItem::Empty);
+    }
+
+    /// Put the hero on an empty cell. Fails if the map already has a hero.
+    pub fn place_hero(&mut self, position: [usize; 2]) -> Result<(), MapEditError> {
+        return self.place(position, MapItem::HeroEntity);
+    }
+
+    /// Put the goal on an empty cell. Fails if the map already has a goal.
+    pub fn place_goal(&mut self, position: [usize; 2]) -> Result<(), MapEditError> {
+        return self.place(position, MapItem::Goal);
+    }
+
+    /// Put an enemy on an empty cell.
+    pub fn place_enemy(&mut self, position: [usize; 2]) -> Result<(), MapEditError> {
+        return self.place(position, MapItem::EnemyEntity);
+    }
+
+    /// Take the hero, goal or enemy off `position`, leaving it empty.
+    pub fn remove_entity(&mut self, position: [usize; 2]) -> Result<MapItem, MapEditError> {
+        let current = self.get(position)?;
+        if !is_entity(current) {
+            return Err(MapEditError::NoEntity { position });
+        }
+        self.step(|editor| editor.write(position, MapItem::Empty))?;
+        return Ok(current);
+    }
+
+    /// Move the hero, goal or enemy at `from` onto the empty cell `to`.
+    pub fn move_entity(&mut self, from: [usize; 2], to: [usize; 2]) -> Result<(), MapEditError> {
+        let entity = self.get(from)?;
+        if !is_entity(entity) {
+            return Err(MapEditError::NoEntity { position: from });
+        }
+        let target = self.get(to)?;
+        if target != MapItem::Empty {
+            return Err(MapEditError::Occupied {
+                position: to,
+                item: target,
+            });
+        }
+        return self.step(|editor| {
+            editor.write(from, MapItem::Empty);
+            editor.write(to, entity);
+        });
+    }
+
+    /// Run several edits as one undoable step. If `edits` returns an error, every edit it made
+    /// is rolled back and the error is passed on.
+    pub fn batch<F>(&mut self, edits: F) -> Result<(), MapEditError>
+    where
+        F: FnOnce(&mut MapEditor) -> Result<(), MapEditError>,
+    {
+        let outermost = !self.in_batch;
+        if outermost {
+            self.undo_stack.push(Vec::new());
+            self.in_batch = true;
+        }
+        let rollback_to = self.undo_stack.last().map_or(0, |changes| changes.len());
+        let result = edits(self);
+        if result.is_err() {
+            self.revert_last_step_to(rollback_to);
+        }
+        if outermost {
+            self.in_batch = false;
+            if self
+                .undo_stack
+                .last()
+                .is_some_and(|changes| changes.is_empty())
+            {
+                self.undo_stack.pop();
+            }
+        }
+        return result;
+    }
+
+    /// Undo the most recent edit or batch.
+    pub fn undo(&mut self) -> Result<(), MapEditError> {
+        if self.in_batch || self.undo_stack.is_empty() {
+            return Err(MapEditError::NothingToUndo);
+        }
+        self.revert_last_step_to(0);
+        self.undo_stack.pop();
+        return Ok(());
+    }
+
+    fn place(&mut self, position: [usize; 2], entity: MapItem) -> Result<(), MapEditError> {
+        if !is_entity(entity) {
+            return Err(MapEditError::WrongKind { item: entity });
+        }
+        let current = self.get(position)?;
+        if current != MapItem::Empty {
+            return Err(MapEditError::Occupied {
+                position,
+                item: current,
+            });
+        }
+        if entity != MapItem::EnemyEntity && self.map.find_item(self.grid(), entity).is_some() {
+            return Err(MapEditError::AlreadyPlaced { item: entity });
+        }
+        return self.step(|editor| editor.write(position, entity));
+    }
+
+    /// Apply already-validated writes as their own undo step, or as part of the open batch.
+    fn step<F>(&mut self, writes: F) -> Result<(), MapEditError>
+    where
+        F: FnOnce(&mut MapEditor),
+    {
+        if !self.in_batch {
+            self.undo_stack.push(Vec::new());
+        }
+        writes(self);
+        return Ok(());
+    }
+
+    fn write(&mut self, [pos_x, pos_y]: [usize; 2], item: MapItem) {
+        let grid = self
+            .map
+            .map
+            .as_mut()
+            .expect("Editor maps always have a grid");
+        let previous = grid[pos_x][pos_y];
+        grid[pos_x][pos_y] = item;
+        self.undo_stack
+            .last_mut()
+            .expect("Writes happen inside a step")
+            .push(([pos_x, pos_y], previous));
+    }
+
+    /// Restore the cells changed by the last step, newest first, until only `keep` of its
+    /// changes are left.
+    fn revert_last_step_to(&mut self, keep: usize) {
+        let Some(changes) = self.undo_stack.last_mut() else {
+            return;
+        };
+        let grid = self
+            .map
+            .map
+            .as_mut()
+            .expect("Editor maps always have a grid");
+        while changes.len() > keep {
+            let ([pos_x, pos_y], previous) = changes.pop().unwrap();
+            grid[pos_x][pos_y] = previous;
+        }
+    }
+
+    fn grid(&self) -> &MapGrid {
+        return self
+            .map
+            .map
+            .as_ref()
+            .expect("Editor maps always have a grid");
+    }
+
+    fn check_bounds(&self, position: [usize; 2]) -> Result<(), MapEditError> {
+        if position[0] >= self.map.dimx() || position[1] >= self.map.dimy() {
+            return Err(MapEditError::OutOfBounds { position });
+        }
+        return Ok(());
+    }
+}
+
+fn is_entity(item: MapItem) -> bool {
+    return matches!(
+        item,
+        MapItem::HeroEntity | MapItem::Goal | MapItem::EnemyEntity
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapGenerationOptions;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn generated_editor() -> MapEditor {
+        let mut game_map = Map::new(16, 16);
+        game_map.map = Some(
+            game_map
+                .generate_map(
+                    &MapGenerationOptions::default(),
+                    &mut StdRng::seed_from_u64(5),
+                )
+                .unwrap(),
+        );
+        return MapEditor::new(game_map);
+    }
+
+    fn free_cells(editor: &MapEditor) -> Vec<[usize; 2]> {
+        return editor.map().list_unoccupied_positions(editor.grid());
+    }
+
+    #[test]
+    fn undo_restores_the_map_exactly() {
+        let mut editor = generated_editor();
+        let original = editor.grid().clone();
+        let hero = editor
+            .map()
+            .find_item(editor.grid(), MapItem::HeroEntity)
+            .unwrap();
+        let free = free_cells(&editor);
+
+        editor.move_entity(hero, free[0]).unwrap();
+        editor.place_enemy(free[1]).unwrap();
+        editor.set_terrain(free[2], MapItem::Junk).unwrap();
+        editor
+            .batch(|editor| {
+                editor.remove_entity(free[1])?;
+                editor.set_terrain(free[1], MapItem::Obstacle)?;
+                return editor.set_terrain(free[3], MapItem::Obstacle);
+            })
+            .unwrap();
+        assert_ne!(editor.grid(), &original);
+
+        for _ in 0..4 {
+            editor.undo().unwrap();
+        }
+        assert_eq!(editor.grid(), &original);
+        assert_eq!(editor.undo(), Err(MapEditError::NothingToUndo));
+    }
+
+    #[test]
+    fn failed_batch_rolls_back() {
+        let mut editor = generated_editor();
+        let free = free_cells(&editor);
+        editor.place_enemy(free[0]).unwrap();
+        let before = editor.grid().clone();
+
+        let result = editor.batch(|editor| {
+            editor.set_terrain(free[1], MapItem::Obstacle)?;
+            editor.move_entity(free[0], free[2])?;
+            // A nested batch that succeeds is still undone with the outer one.
+            editor.batch(|editor| editor.place_enemy(free[3]))?;
+            return editor.place_hero(free[4]);
+        });
+        assert_eq!(
+            result,
+            Err(MapEditError::AlreadyPlaced {
+                item: MapItem::HeroEntity
+            })
+        );
+        assert_eq!(editor.grid(), &before);
+
+        // The failed batch leaves no undo step behind, so undo reverts the enemy placement.
+        editor.undo().unwrap();
+        assert_eq!(editor.get(free[0]), Ok(MapItem::Empty));
+    }
+}
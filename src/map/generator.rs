@@ -0,0 +1,95 @@
+// Pluggable obstacle generators
+
+use rand::rngs::StdRng;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::map::{
+    CaveGenerator, DungeonGenerator, Map, MapGenerationError, MapGenerationOptions, MapGrid,
+    MazeGenerator, PolyominoLibrary, WfcGenerator,
+};
+
+/// Lays out the obstacles of a map, before `Map::generate_map` places the hero, goal and
+/// enemies. Generator-specific settings live on the implementing type; the shared ones, such
+/// as `wall_coverage`, come from `options`.
+///
+/// Implement this outside the crate's map module to add a generator, then set it as
+/// `MapGenerationOptions::obstacle_generator` or register it by name.
+pub trait MapGenerator: Debug {
+    /// Build a grid the size of `map` holding only `Empty` and `Obstacle` cells. All
+    /// randomness must come from `rng` so seeded games stay reproducible.
+    fn generate(
+        &self,
+        map: &Map,
+        options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError>;
+}
+
+/// Generators that can be selected by name.
+#[derive(Clone, Debug)]
+pub struct MapGeneratorRegistry {
+    generators: Vec<(String, Rc<dyn MapGenerator>)>,
+}
+
+impl Default for MapGeneratorRegistry {
+    /// The built-in generators with their default settings: `tetromino`, `pentomino`,
+    /// `walls`, `maze`, `cave`, `dungeon` and `wfc`.
+    fn default() -> MapGeneratorRegistry {
+        let mut registry = MapGeneratorRegistry {
+            generators: Vec::new(),
+        };
+        registry.register("tetromino", Rc::new(PolyominoLibrary::default()));
+        for (name, profile) in [("pentomino", "pentominoes"), ("walls", "walls")] {
+            let library =
+                PolyominoLibrary::built_in(profile).expect("Built-in profile should parse");
+            registry.register(name, Rc::new(library));
+        }
+        registry.register("maze", Rc::new(MazeGenerator::default()));
+        registry.register("cave", Rc::new(CaveGenerator::default()));
+        registry.register("dungeon", Rc::new(DungeonGenerator::default()));
+        registry.register("wfc", Rc::new(WfcGenerator::default()));
+        return registry;
+    }
+}
+
+impl MapGeneratorRegistry {
+    /// Add a generator under `name`, replacing any generator already registered there.
+    pub fn register(&mut self, name: &str, generator: Rc<dyn MapGenerator>) {
+        self.generators.retain(|(existing, _)| existing != name);
+        self.generators.push((name.to_string(), generator));
+    }
+
+    /// Look up a generator by name.
+    pub fn get(&self, name: &str) -> Option<Rc<dyn MapGenerator>> {
+        return self
+            .generators
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, generator)| Rc::clone(generator));
+    }
+
+    /// Names of every registered generator, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        return self
+            .generators
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+    }
+}
+
+impl MapGenerationOptions {
+    /// Switch to the generator registered under `name`.
+    pub fn select_generator(
+        &mut self,
+        name: &str,
+        registry: &MapGeneratorRegistry,
+    ) -> Result<(), MapGenerationError> {
+        let Some(generator) = registry.get(name) else {
+            return Err(MapGenerationError::UnknownGenerator);
+        };
+        self.obstacle_generator = generator;
+        return Ok(());
+    }
+}
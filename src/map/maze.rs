@@ -0,0 +1,257 @@
+// Maze obstacle generators
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use crate::map::{Map, MapGenerationError, MapGenerationOptions, MapGenerator, MapGrid, MapItem};
+
+/// Carving strategy used to build the spanning tree of the maze.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MazeAlgorithm {
+    /// Depth-first carving. Produces long, winding corridors with few branches.
+    RecursiveBacktracker,
+    /// Randomized Prim's algorithm. Produces many short branches and dead ends.
+    Prim,
+}
+
+impl MazeAlgorithm {
+    /// Look up an algorithm by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<MazeAlgorithm> {
+        match name {
+            "backtracker" => {
+                return Some(MazeAlgorithm::RecursiveBacktracker);
+            }
+            "prim" => {
+                return Some(MazeAlgorithm::Prim);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// Settings for the maze obstacle generator.
+///
+/// Maze cells sit on odd coordinates with walls in between, so the corridors are one cell
+/// wide. With a `loop_ratio` of 0 the result is a perfect maze (exactly one path between any
+/// two cells). Higher ratios braid the maze by opening that fraction of its dead ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MazeGenerator {
+    pub algorithm: MazeAlgorithm,
+    pub loop_ratio: f32,
+}
+
+impl Default for MazeGenerator {
+    fn default() -> MazeGenerator {
+        MazeGenerator {
+            algorithm: MazeAlgorithm::RecursiveBacktracker,
+            loop_ratio: 0.,
+        }
+    }
+}
+
+impl MapGenerator for MazeGenerator {
+    /// Build the obstacle layout for a maze the size of `map`.
+    fn generate(
+        &self,
+        map: &Map,
+        _options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if self.loop_ratio.clamp(0., 1.) != self.loop_ratio || map.dimx() < 3 || map.dimy() < 3 {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+        let mut working_map = map.filled_grid(MapItem::Obstacle);
+        let cells = [(map.dimx() - 1) / 2, (map.dimy() - 1) / 2];
+
+        match self.algorithm {
+            MazeAlgorithm::RecursiveBacktracker => {
+                carve_backtracker(&mut working_map, cells, rng);
+            }
+            MazeAlgorithm::Prim => {
+                carve_prim(&mut working_map, cells, rng);
+            }
+        }
+        braid(&mut working_map, cells, self.loop_ratio, rng);
+
+        return Ok(working_map);
+    }
+}
+
+/// Map coordinates of the maze cell at `cell`.
+fn cell_to_map([cell_x, cell_y]: [usize; 2]) -> [usize; 2] {
+    return [cell_x * 2 + 1, cell_y * 2 + 1];
+}
+
+/// Maze cells that share a wall with `cell`.
+fn cell_neighbors([cell_x, cell_y]: [usize; 2], cells: [usize; 2]) -> Vec<[usize; 2]> {
+    let mut neighbors: Vec<[usize; 2]> = Vec::new();
+    if cell_x > 0 {
+        neighbors.push([cell_x - 1, cell_y]);
+    }
+    if cell_y > 0 {
+        neighbors.push([cell_x, cell_y - 1]);
+    }
+    if cell_x + 1 < cells[0] {
+        neighbors.push([cell_x + 1, cell_y]);
+    }
+    if cell_y + 1 < cells[1] {
+        neighbors.push([cell_x, cell_y + 1]);
+    }
+    return neighbors;
+}
+
+/// Open a maze cell and the wall between it and `from`.
+fn open_passage(working_map: &mut MapGrid, from: [usize; 2], to: [usize; 2]) {
+    let [from_x, from_y] = cell_to_map(from);
+    let [to_x, to_y] = cell_to_map(to);
+    working_map[(from_x + to_x) / 2][(from_y + to_y) / 2] = MapItem::Empty;
+    working_map[to_x][to_y] = MapItem::Empty;
+}
+
+fn is_passage_open(working_map: &MapGrid, from: [usize; 2], to: [usize; 2]) -> bool {
+    let [from_x, from_y] = cell_to_map(from);
+    let [to_x, to_y] = cell_to_map(to);
+    return working_map[(from_x + to_x) / 2][(from_y + to_y) / 2] == MapItem::Empty;
+}
+
+fn random_cell(cells: [usize; 2], rng: &mut StdRng) -> [usize; 2] {
+    return [rng.random_range(0..cells[0]), rng.random_range(0..cells[1])];
+}
+
+fn carve_backtracker(working_map: &mut MapGrid, cells: [usize; 2], rng: &mut StdRng) {
+    let mut visited: Vec<Vec<bool>> = vec![vec![false; cells[1]]; cells[0]];
+    let start = random_cell(cells, rng);
+    let [start_x, start_y] = cell_to_map(start);
+    working_map[start_x][start_y] = MapItem::Empty;
+    visited[start[0]][start[1]] = true;
+
+    let mut stack: Vec<[usize; 2]> = vec![start];
+    while let Some(&current) = stack.last() {
+        let mut unvisited: Vec<[usize; 2]> = cell_neighbors(current, cells)
+            .into_iter()
+            .filter(|[n_x, n_y]| !visited[*n_x][*n_y])
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        unvisited.shuffle(rng);
+        let next = unvisited[0];
+        open_passage(working_map, current, next);
+        visited[next[0]][next[1]] = true;
+        stack.push(next);
+    }
+}
+
+fn carve_prim(working_map: &mut MapGrid, cells: [usize; 2], rng: &mut StdRng) {
+    let mut in_maze: Vec<Vec<bool>> = vec![vec![false; cells[1]]; cells[0]];
+    let start = random_cell(cells, rng);
+    let [start_x, start_y] = cell_to_map(start);
+    working_map[start_x][start_y] = MapItem::Empty;
+    in_maze[start[0]][start[1]] = true;
+
+    // Each entry is a wall, stored as the maze cell it belongs to and the cell behind it.
+    let mut walls: Vec<([usize; 2], [usize; 2])> = cell_neighbors(start, cells)
+        .into_iter()
+        .map(|neighbor| (start, neighbor))
+        .collect();
+    while !walls.is_empty() {
+        let (from, to) = walls.swap_remove(rng.random_range(0..walls.len()));
+        if in_maze[to[0]][to[1]] {
+            continue;
+        }
+        open_passage(working_map, from, to);
+        in_maze[to[0]][to[1]] = true;
+        for neighbor in cell_neighbors(to, cells) {
+            if !in_maze[neighbor[0]][neighbor[1]] {
+                walls.push((to, neighbor));
+            }
+        }
+    }
+}
+
+/// Knock a wall out of roughly `loop_ratio` of the dead ends, creating loops.
+fn braid(working_map: &mut MapGrid, cells: [usize; 2], loop_ratio: f32, rng: &mut StdRng) {
+    if loop_ratio <= 0. {
+        return;
+    }
+    for cell_x in 0..cells[0] {
+        for cell_y in 0..cells[1] {
+            let cell = [cell_x, cell_y];
+            let neighbors = cell_neighbors(cell, cells);
+            let (open, mut closed): (Vec<[usize; 2]>, Vec<[usize; 2]>) = neighbors
+                .into_iter()
+                .partition(|neighbor| is_passage_open(working_map, cell, *neighbor));
+            if open.len() != 1 || closed.is_empty() || !rng.random_bool(loop_ratio as f64) {
+                continue;
+            }
+            closed.shuffle(rng);
+            open_passage(working_map, cell, closed[0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn count_open(working_map: &MapGrid) -> usize {
+        return working_map
+            .iter()
+            .flatten()
+            .filter(|item| **item == MapItem::Empty)
+            .count();
+    }
+
+    #[test]
+    fn maze_without_loops_is_perfect() {
+        for algorithm in [MazeAlgorithm::RecursiveBacktracker, MazeAlgorithm::Prim] {
+            for [dimx, dimy] in [[21, 21], [20, 15], [3, 3]] {
+                let map = Map::new(dimx, dimy);
+                let generator = MazeGenerator {
+                    algorithm,
+                    loop_ratio: 0.,
+                };
+                let working_map = generator
+                    .generate(
+                        &map,
+                        &MapGenerationOptions::default(),
+                        &mut StdRng::seed_from_u64(1),
+                    )
+                    .unwrap();
+                // A spanning tree over n cells opens the n cells and n - 1 walls between them,
+                // all reachable from one another.
+                let num_cells = ((dimx - 1) / 2) * ((dimy - 1) / 2);
+                assert_eq!(count_open(&working_map), 2 * num_cells - 1);
+                let reachable = map
+                    .distance_field(&working_map, cell_to_map([0, 0]))
+                    .iter()
+                    .flatten()
+                    .filter(|distance| distance.is_some())
+                    .count();
+                assert_eq!(reachable, 2 * num_cells - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn braiding_opens_extra_walls() {
+        let map = Map::new(21, 21);
+        let generator = MazeGenerator {
+            algorithm: MazeAlgorithm::RecursiveBacktracker,
+            loop_ratio: 1.,
+        };
+        let working_map = generator
+            .generate(
+                &map,
+                &MapGenerationOptions::default(),
+                &mut StdRng::seed_from_u64(1),
+            )
+            .unwrap();
+        assert!(count_open(&working_map) > 2 * 100 - 1);
+    }
+}
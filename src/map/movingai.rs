@@ -0,0 +1,257 @@
+// MovingAI grid benchmark map and scenario import
+// Format reference: https://movingai.com/benchmarks/formats.html
+
+use crate::map::{Map, MapGrid, MapItem};
+
+#[derive(Debug, PartialEq)]
+pub enum MovingAiError {
+    /// The `.map` header was missing a `type`, `height`, `width` or `map` line, or one of them
+    /// was malformed.
+    InvalidHeader { line: usize },
+    /// A terrain character that isn't part of the format.
+    UnknownTerrain {
+        line: usize,
+        column: usize,
+        glyph: char,
+    },
+    /// The number of rows or columns didn't match the header.
+    WrongSize,
+    /// A `.scen` line didn't have nine tab-separated fields of the right types.
+    InvalidScenario { line: usize },
+    /// The scenario was written for a map of a different size.
+    ScenarioMapMismatch,
+    /// The scenario's start or goal is outside the map, on impassable terrain, or on the
+    /// other endpoint.
+    BlockedEndpoint { position: [usize; 2] },
+}
+
+/// One start/goal pair from a `.scen` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MovingAiScenario {
+    pub bucket: u32,
+    pub map_path: String,
+    pub map_dims: [usize; 2],
+    pub start: [usize; 2],
+    pub goal: [usize; 2],
+    /// Published optimal length. MovingAI measures octile paths (diagonal moves cost √2),
+    /// so the hero's 4-connected path can never be shorter.
+    pub optimal_length: f64,
+}
+
+impl Map {
+    /// Load the terrain of a MovingAI `.map` file. Passable terrain (`.`, `G`, `S`) becomes
+    /// `Empty`, while out-of-bounds (`@`, `O`), trees (`T`) and water (`W`) become `Obstacle`.
+    pub fn from_movingai_map(text: &str) -> Result<Map, MovingAiError> {
+        let mut lines = text.lines().enumerate();
+        let mut dims: [Option<usize>; 2] = [None, None];
+        loop {
+            let Some((line_idx, line)) = lines.next() else {
+                return Err(MovingAiError::InvalidHeader {
+                    line: text.lines().count() + 1,
+                });
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("type"), Some(_)) => {}
+                (Some("width"), Some(value)) => dims[0] = value.parse().ok(),
+                (Some("height"), Some(value)) => dims[1] = value.parse().ok(),
+                (Some("map"), None) => break,
+                _ => {
+                    return Err(MovingAiError::InvalidHeader { line: line_idx + 1 });
+                }
+            }
+        }
+        let [Some(dimx), Some(dimy)] = dims else {
+            return Err(MovingAiError::InvalidHeader { line: 1 });
+        };
+        if dimx == 0 || dimy == 0 {
+            return Err(MovingAiError::WrongSize);
+        }
+
+        let mut map = Map::new(dimx, dimy);
+        let mut grid: MapGrid = map.filled_grid(MapItem::Obstacle);
+        let mut rows_read = 0;
+        for (line_idx, line) in lines {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if rows_read == dimy || line.chars().count() != dimx {
+                return Err(MovingAiError::WrongSize);
+            }
+            for (pos_x, glyph) in line.chars().enumerate() {
+                grid[pos_x][rows_read] = match glyph {
+                    '.' | 'G' | 'S' => MapItem::Empty,
+                    '@' | 'O' | 'T' | 'W' => MapItem::Obstacle,
+                    _ => {
+                        return Err(MovingAiError::UnknownTerrain {
+                            line: line_idx + 1,
+                            column: pos_x + 1,
+                            glyph,
+                        });
+                    }
+                };
+            }
+            rows_read += 1;
+        }
+        if rows_read != dimy {
+            return Err(MovingAiError::WrongSize);
+        }
+        map.map = Some(grid);
+        return Ok(map);
+    }
+
+    /// Put the hero at the scenario's start and the goal at its end.
+    pub fn place_movingai_scenario(
+        &mut self,
+        scenario: &MovingAiScenario,
+    ) -> Result<(), MovingAiError> {
+        if scenario.map_dims != [self.dimx, self.dimy] {
+            return Err(MovingAiError::ScenarioMapMismatch);
+        }
+        let Some(grid) = self.map.as_mut() else {
+            return Err(MovingAiError::ScenarioMapMismatch);
+        };
+        if scenario.start == scenario.goal {
+            return Err(MovingAiError::BlockedEndpoint {
+                position: scenario.goal,
+            });
+        }
+        for position in [scenario.start, scenario.goal] {
+            if position[0] >= self.dimx
+                || position[1] >= self.dimy
+                || grid[position[0]][position[1]] != MapItem::Empty
+            {
+                return Err(MovingAiError::BlockedEndpoint { position });
+            }
+        }
+        grid[scenario.start[0]][scenario.start[1]] = MapItem::HeroEntity;
+        grid[scenario.goal[0]][scenario.goal[1]] = MapItem::Goal;
+        return Ok(());
+    }
+}
+
+/// Parse every start/goal pair in a MovingAI `.scen` file. Pairs that can't be played, such as
+/// a start on the goal, are still returned; `place_movingai_scenario` rejects them.
+pub fn parse_movingai_scenarios(text: &str) -> Result<Vec<MovingAiScenario>, MovingAiError> {
+    let mut scenarios: Vec<MovingAiScenario> = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with("version") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(|field| field.trim()).collect();
+        let invalid = MovingAiError::InvalidScenario { line: line_idx + 1 };
+        if fields.len() != 9 {
+            return Err(invalid);
+        }
+        let mut numbers: Vec<usize> = Vec::new();
+        for field in [
+            fields[2], fields[3], fields[4], fields[5], fields[6], fields[7],
+        ] {
+            let Ok(number) = field.parse::<usize>() else {
+                return Err(invalid);
+            };
+            numbers.push(number);
+        }
+        let (Ok(bucket), Ok(optimal_length)) = (fields[0].parse(), fields[8].parse()) else {
+            return Err(invalid);
+        };
+        scenarios.push(MovingAiScenario {
+            bucket,
+            map_path: fields[1].to_string(),
+            map_dims: [numbers[0], numbers[1]],
+            start: [numbers[2], numbers[3]],
+            goal: [numbers[4], numbers[5]],
+            optimal_length,
+        });
+    }
+    return Ok(scenarios);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "type octile\nheight 3\nwidth 4\nmap\n..T.\n.@@G\nS..W\n";
+
+    fn scenario_line(start: [usize; 2], goal: [usize; 2]) -> String {
+        return format!(
+            "0\tsmall.map\t4\t3\t{}\t{}\t{}\t{}\t5.00000000",
+            start[0], start[1], goal[0], goal[1]
+        );
+    }
+
+    #[test]
+    fn map_terrain_is_read_row_by_row() {
+        let map = Map::from_movingai_map(MAP).unwrap();
+        assert_eq!([map.dimx(), map.dimy()], [4, 3]);
+        let grid = map.map.as_ref().unwrap();
+        assert_eq!(grid[2][0], MapItem::Obstacle);
+        assert_eq!(grid[1][1], MapItem::Obstacle);
+        assert_eq!(grid[3][1], MapItem::Empty);
+        assert_eq!(grid[0][2], MapItem::Empty);
+        assert_eq!(grid[3][2], MapItem::Obstacle);
+        assert_eq!(
+            Map::from_movingai_map("type octile\nheight 1\nwidth 2\nmap\n.X\n").err(),
+            Some(MovingAiError::UnknownTerrain {
+                line: 5,
+                column: 2,
+                glyph: 'X'
+            })
+        );
+        assert_eq!(
+            Map::from_movingai_map("type octile\nheight 2\nwidth 2\nmap\n..\n").err(),
+            Some(MovingAiError::WrongSize)
+        );
+    }
+
+    #[test]
+    fn scenarios_parse_and_place() {
+        let text = format!("version 1\n{}\n", scenario_line([0, 0], [3, 1]));
+        let scenarios = parse_movingai_scenarios(&text).unwrap();
+        assert_eq!(
+            scenarios,
+            vec![MovingAiScenario {
+                bucket: 0,
+                map_path: "small.map".to_string(),
+                map_dims: [4, 3],
+                start: [0, 0],
+                goal: [3, 1],
+                optimal_length: 5.,
+            }]
+        );
+        let mut map = Map::from_movingai_map(MAP).unwrap();
+        map.place_movingai_scenario(&scenarios[0]).unwrap();
+        assert!(map.validate_level().is_ok());
+    }
+
+    #[test]
+    fn degenerate_and_blocked_scenarios_are_rejected() {
+        // A start on the goal parses, so the rest of the file can still be run, but can't be
+        // placed.
+        let text = format!(
+            "version 1\n{}\n{}\n",
+            scenario_line([1, 0], [1, 0]),
+            scenario_line([0, 0], [3, 1])
+        );
+        let scenarios = parse_movingai_scenarios(&text).unwrap();
+        assert_eq!(scenarios.len(), 2);
+        let mut map = Map::from_movingai_map(MAP).unwrap();
+        assert_eq!(
+            map.place_movingai_scenario(&scenarios[0]),
+            Err(MovingAiError::BlockedEndpoint { position: [1, 0] })
+        );
+        assert_eq!(map.map.as_ref().unwrap()[1][0], MapItem::Empty);
+        assert_eq!(
+            parse_movingai_scenarios("0\tsmall.map\t4\t3\t0\t0\n"),
+            Err(MovingAiError::InvalidScenario { line: 1 })
+        );
+
+        let blocked = parse_movingai_scenarios(&scenario_line([0, 0], [2, 0])).unwrap();
+        let mut map = Map::from_movingai_map(MAP).unwrap();
+        assert_eq!(
+            map.place_movingai_scenario(&blocked[0]),
+            Err(MovingAiError::BlockedEndpoint { position: [2, 0] })
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// Strategies and constraints for placing the hero, goal and enemies
+
+/// How enemies are spread over the open cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnemyPlacement {
+    /// Every open cell is equally likely.
+    Uniform,
+    /// Poisson-disk style spreading: no two enemies closer than `min_spacing` cells
+    /// (straight-line distance).
+    Spread { min_spacing: f32 },
+    /// Enemies cluster around the goal, within `radius` steps of it along open cells.
+    GuardGoal { radius: u32 },
+}
+
+/// Where the goal goes relative to the hero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GoalPlacement {
+    /// Any open cell that satisfies the distance constraint is equally likely.
+    Uniform,
+    /// The reachable cell with the longest path from the hero.
+    Farthest,
+}
+
+impl GoalPlacement {
+    /// Look up a goal placement by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<GoalPlacement> {
+        match name {
+            "uniform" => {
+                return Some(GoalPlacement::Uniform);
+            }
+            "farthest" => {
+                return Some(GoalPlacement::Farthest);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// Controls scenario difficulty by constraining where entities spawn.
+///
+/// The hero is placed first, then the goal, then the enemies. `min_hero_enemy_distance` is a
+/// Manhattan distance, since enemies chase the hero in straight lines regardless of walls.
+/// Both distances, and enemy spacing, go the short way around on a torus.
+/// `min_hero_goal_path_distance` is measured along open cells, so a non-zero value also
+/// guarantees the goal is reachable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlacementOptions {
+    pub enemy_placement: EnemyPlacement,
+    pub goal_placement: GoalPlacement,
+    pub min_hero_enemy_distance: u32,
+    pub min_hero_goal_path_distance: u32,
+}
+
+impl Default for PlacementOptions {
+    fn default() -> PlacementOptions {
+        PlacementOptions {
+            enemy_placement: EnemyPlacement::Uniform,
+            goal_placement: GoalPlacement::Uniform,
+            min_hero_enemy_distance: 0,
+            min_hero_goal_path_distance: 0,
+        }
+    }
+}
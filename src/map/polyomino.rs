@@ -0,0 +1,374 @@
+// Polyomino shape libraries for the scattered-obstacle generator
+
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand::rngs::StdRng;
+
+use crate::map::{Map, MapGenerationError, MapGenerationOptions, MapGenerator, MapGrid, MapItem};
+
+/// A single obstacle shape. `cells[i][j]` is true where the shape covers the cell at
+/// offset `[i, j]` from its corner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polyomino {
+    pub name: String,
+    pub cells: Vec<Vec<bool>>,
+    pub weight: f32,
+}
+
+impl Polyomino {
+    /// Number of cells the shape covers.
+    pub fn area(&self) -> usize {
+        return self.cells.iter().flatten().filter(|cell| **cell).count();
+    }
+}
+
+/// The set of shapes the scattered-obstacle generator picks from, each with probability
+/// proportional to its weight.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolyominoLibrary {
+    pub shapes: Vec<Polyomino>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PolyominoParseError {
+    /// Shape rows appeared before any `shape` header.
+    RowOutsideShape { line: usize },
+    /// A `shape` header without a name, or with a weight that isn't a non-negative number.
+    InvalidHeader { line: usize },
+    /// A row contained something other than `X` or `.`.
+    InvalidCell { line: usize },
+    /// A row was a different length from the first row of its shape.
+    RaggedRows { line: usize },
+    /// A shape had no rows, or no `X` cells.
+    EmptyShape { name: String },
+    /// The text didn't define any shapes.
+    NoShapes,
+}
+
+/// Obstacle profiles that ship with the game, by name.
+const BUILT_IN_PROFILES: [(&str, &str); 3] = [
+    (
+        "tetrominoes",
+        include_str!("../../obstacle_profiles/tetrominoes.txt"),
+    ),
+    (
+        "pentominoes",
+        include_str!("../../obstacle_profiles/pentominoes.txt"),
+    ),
+    ("walls", include_str!("../../obstacle_profiles/walls.txt")),
+];
+
+impl Default for PolyominoLibrary {
+    /// The seven tetrominoes with equal weights.
+    fn default() -> PolyominoLibrary {
+        return PolyominoLibrary::built_in("tetrominoes").expect("Built-in profile should parse");
+    }
+}
+
+impl PolyominoLibrary {
+    /// Load one of the profiles in `obstacle_profiles/` by file name, without the extension.
+    pub fn built_in(name: &str) -> Option<PolyominoLibrary> {
+        let (_, text) = BUILT_IN_PROFILES
+            .iter()
+            .find(|(profile_name, _)| *profile_name == name)?;
+        return PolyominoLibrary::parse(text).ok();
+    }
+
+    /// Parse a shape library from text.
+    ///
+    /// Lines starting with `#` are comments and blank lines are ignored. Each shape starts with
+    /// a header `shape <name> [weight]` (the weight defaults to 1), followed by one line per
+    /// row where `X` marks a covered cell and `.` an uncovered one.
+    pub fn parse(text: &str) -> Result<PolyominoLibrary, PolyominoParseError> {
+        let mut shapes: Vec<Polyomino> = Vec::new();
+        for (line_idx, raw_line) in text.lines().enumerate() {
+            let line_number = line_idx + 1;
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            if words.next() == Some("shape") {
+                let name = words.next();
+                let weight = match words.next() {
+                    Some(weight) => weight.parse::<f32>().ok(),
+                    None => Some(1.),
+                };
+                match (name, weight, words.next()) {
+                    (Some(name), Some(weight), None) if weight >= 0. && weight.is_finite() => {
+                        shapes.push(Polyomino {
+                            name: name.to_string(),
+                            cells: Vec::new(),
+                            weight,
+                        });
+                    }
+                    _ => {
+                        return Err(PolyominoParseError::InvalidHeader { line: line_number });
+                    }
+                }
+                continue;
+            }
+
+            let Some(shape) = shapes.last_mut() else {
+                return Err(PolyominoParseError::RowOutsideShape { line: line_number });
+            };
+            let mut row: Vec<bool> = Vec::new();
+            for cell in line.chars() {
+                match cell {
+                    'X' => row.push(true),
+                    '.' => row.push(false),
+                    _ => {
+                        return Err(PolyominoParseError::InvalidCell { line: line_number });
+                    }
+                }
+            }
+            if shape
+                .cells
+                .first()
+                .is_some_and(|first| first.len() != row.len())
+            {
+                return Err(PolyominoParseError::RaggedRows { line: line_number });
+            }
+            shape.cells.push(row);
+        }
+
+        if shapes.is_empty() {
+            return Err(PolyominoParseError::NoShapes);
+        }
+        if let Some(empty) = shapes.iter().find(|shape| shape.area() == 0) {
+            return Err(PolyominoParseError::EmptyShape {
+                name: empty.name.clone(),
+            });
+        }
+        return Ok(PolyominoLibrary { shapes });
+    }
+
+    /// Write the library in the format `parse` reads.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for shape in &self.shapes {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("shape {} {}\n", shape.name, shape.weight));
+            for row in &shape.cells {
+                for cell in row {
+                    text.push(if *cell { 'X' } else { '.' });
+                }
+                text.push('\n');
+            }
+        }
+        return text;
+    }
+
+    /// Average number of cells covered by one randomly picked shape.
+    pub fn expected_area(&self) -> f32 {
+        let total_weight: f32 = self.shapes.iter().map(|shape| shape.weight).sum();
+        let weighted_area: f32 = self
+            .shapes
+            .iter()
+            .map(|shape| shape.weight * shape.area() as f32)
+            .sum();
+        return weighted_area / total_weight;
+    }
+}
+
+impl MapGenerator for PolyominoLibrary {
+    /// Scatter randomly rotated shapes from the library until the fraction of obstacle cells
+    /// is within `options.coverage_tolerance` of `options.wall_coverage`.
+    fn generate(
+        &self,
+        map: &Map,
+        options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        let mut working_map: MapGrid = map.filled_grid(MapItem::Empty);
+        let [wall_coverage, coverage_tolerance] =
+            [options.wall_coverage, options.coverage_tolerance];
+        let [dimx, dimy] = [map.dimx(), map.dimy()];
+
+        if wall_coverage.clamp(0., 1.) != wall_coverage
+            || coverage_tolerance.max(0.) != coverage_tolerance
+        {
+            return Err(MapGenerationError::InvalidWallCoverage);
+        }
+
+        let weights = self.shapes.iter().map(|shape| shape.weight);
+        let Ok(shape_dist) = WeightedIndex::new(weights) else {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        };
+
+        // Overlapping shapes cover less than their total area, so keep placing shapes until the
+        // measured coverage reaches the target instead of trusting a shape count. Placements
+        // that would overshoot past the tolerance are skipped.
+        let total_cells = (dimx * dimy) as f32;
+        let target_obstacle_cells = (total_cells * wall_coverage).round() as usize;
+        let min_obstacle_cells =
+            (total_cells * (wall_coverage - coverage_tolerance)).ceil() as usize;
+        let max_obstacle_cells = (total_cells * (wall_coverage + coverage_tolerance)) as usize;
+        let max_attempts = 16 * dimx * dimy;
+        let mut obstacle_cells: usize = 0;
+        let mut attempts: usize = 0;
+
+        while obstacle_cells < target_obstacle_cells {
+            if attempts >= max_attempts {
+                if obstacle_cells >= min_obstacle_cells {
+                    break;
+                }
+                return Err(MapGenerationError::CoverageUnreachable);
+            }
+            attempts += 1;
+
+            let shape = &self.shapes[shape_dist.sample(&mut *rng)];
+            let cells = &shape.cells;
+            let rotation = rng.random_range(0..=3);
+
+            // Shapes from custom libraries can be too long for small maps in some rotations.
+            let [extent_x, extent_y] = match rotation {
+                0 | 1 => [cells.len(), cells[0].len()],
+                _ => [cells[0].len(), cells.len()],
+            };
+            if extent_x > dimx || extent_y > dimy {
+                continue;
+            }
+            let pos = [
+                rng.random_range(0..=dimx - extent_x),
+                rng.random_range(0..=dimy - extent_y),
+            ];
+
+            let mut footprint: Vec<[usize; 2]> = Vec::new();
+            for i in 0..cells.len() {
+                for j in 0..cells[0].len() {
+                    if !cells[i][j] {
+                        continue;
+                    }
+                    match rotation {
+                        0 => {
+                            // no rotation
+                            footprint.push([pos[0] + i, pos[1] + j]);
+                        }
+                        1 => {
+                            // 180 degree rotation
+                            footprint.push([
+                                pos[0] + cells.len() - i - 1,
+                                pos[1] + cells[0].len() - j - 1,
+                            ]);
+                        }
+                        2 => {
+                            // 90 degree rotation
+                            footprint.push([pos[0] + cells[0].len() - j - 1, pos[1] + i]);
+                        }
+                        3 => {
+                            // 270 degree rotation
+                            footprint.push([pos[0] + j, pos[1] + cells.len() - i - 1]);
+                        }
+                        _ => {
+                            unreachable!()
+                        }
+                    }
+                }
+            }
+
+            let newly_covered = footprint
+                .iter()
+                .filter(|[pos_x, pos_y]| working_map[*pos_x][*pos_y] == MapItem::Empty)
+                .count();
+            if obstacle_cells + newly_covered > max_obstacle_cells {
+                continue;
+            }
+            for [pos_x, pos_y] in footprint {
+                working_map[pos_x][pos_y] = MapItem::Obstacle;
+            }
+            obstacle_cells += newly_covered;
+        }
+
+        return Ok(working_map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn measured_coverage_is_within_tolerance() {
+        let mut game_map = Map::new(64, 48);
+        for profile in ["tetrominoes", "pentominoes", "walls"] {
+            let library = PolyominoLibrary::built_in(profile).unwrap();
+            for wall_coverage in [0.05, 0.25, 0.4] {
+                let options = MapGenerationOptions {
+                    wall_coverage,
+                    coverage_tolerance: 0.01,
+                    ..Default::default()
+                };
+                for seed in 0..3 {
+                    game_map.map = Some(
+                        library
+                            .generate(&game_map, &options, &mut StdRng::seed_from_u64(seed))
+                            .unwrap(),
+                    );
+                    let coverage = game_map.obstacle_coverage().unwrap();
+                    assert!(
+                        (coverage - wall_coverage).abs() <= 0.01,
+                        "{profile} at {wall_coverage}: measured {coverage}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_and_weights_are_parsed() {
+        let text = "# comment\nshape bar 2.5\nXXX\n\nshape corner\nX.\nXX\n";
+        let library = PolyominoLibrary::parse(text).unwrap();
+        assert_eq!(library.shapes.len(), 2);
+        assert_eq!(library.shapes[0].name, "bar");
+        assert_eq!(library.shapes[0].weight, 2.5);
+        assert_eq!(library.shapes[0].cells, vec![vec![true, true, true]]);
+        assert_eq!(library.shapes[1].weight, 1.);
+        assert_eq!(library.shapes[1].area(), 3);
+        assert_eq!(PolyominoLibrary::parse(&library.to_text()), Ok(library));
+    }
+
+    #[test]
+    fn malformed_libraries_are_rejected() {
+        let cases = [
+            (
+                "shape bar\nXXX\nXX\n",
+                PolyominoParseError::RaggedRows { line: 3 },
+            ),
+            (
+                "shape bar\nXOX\n",
+                PolyominoParseError::InvalidCell { line: 2 },
+            ),
+            (
+                "shape bar\nXXX\nshape hole\n...\n",
+                PolyominoParseError::EmptyShape {
+                    name: "hole".to_string(),
+                },
+            ),
+            (
+                "shape bar\nshape dot\nX\n",
+                PolyominoParseError::EmptyShape {
+                    name: "bar".to_string(),
+                },
+            ),
+            ("XXX\n", PolyominoParseError::RowOutsideShape { line: 1 }),
+            (
+                "shape bar -1\nXXX\n",
+                PolyominoParseError::InvalidHeader { line: 1 },
+            ),
+            (
+                "shape bar heavy\nXXX\n",
+                PolyominoParseError::InvalidHeader { line: 1 },
+            ),
+            ("# only a comment\n", PolyominoParseError::NoShapes),
+        ];
+        for (text, error) in cases {
+            assert_eq!(PolyominoLibrary::parse(text), Err(error), "{text:?}");
+        }
+    }
+}
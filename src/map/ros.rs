@@ -0,0 +1,353 @@
+// ROS map_server occupancy grid import/export
+// Format reference: https://wiki.ros.org/map_server#Map_format
+
+use crate::map::{Map, MapGrid, MapItem};
+
+/// Pixel value written for occupied cells.
+const OCCUPIED_PIXEL: u8 = 0;
+/// Pixel value written for free cells. map_server's own map_saver uses 254 rather than 255.
+const FREE_PIXEL: u8 = 254;
+
+#[derive(Debug, PartialEq)]
+pub enum RosMapError {
+    /// The YAML file had a line that isn't `key: value`, or a value of the wrong type.
+    InvalidYaml { line: usize },
+    /// A required YAML key (`image`, `resolution` or `origin`) was missing.
+    MissingKey { key: &'static str },
+    /// The image isn't a PGM (`P2` or `P5`) or its header is malformed.
+    InvalidImage,
+    /// The image had fewer pixels than its header promised.
+    TruncatedImage,
+}
+
+/// The contents of a map_server YAML file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RosMapMetadata {
+    /// Path of the PGM image, relative to the YAML file.
+    pub image: String,
+    /// Metres per cell.
+    pub resolution: f64,
+    /// World pose of the lower-left pixel as `[x, y, yaw]`.
+    pub origin: [f64; 3],
+    /// Whether white means occupied instead of free.
+    pub negate: bool,
+    /// Cells with an occupancy probability above this are obstacles.
+    pub occupied_thresh: f64,
+    /// Cells with an occupancy probability below this are free.
+    pub free_thresh: f64,
+}
+
+impl Default for RosMapMetadata {
+    fn default() -> Self {
+        return RosMapMetadata {
+            image: "map.pgm".to_string(),
+            resolution: 0.05,
+            origin: [0., 0., 0.],
+            negate: false,
+            occupied_thresh: 0.65,
+            free_thresh: 0.196,
+        };
+    }
+}
+
+impl RosMapMetadata {
+    /// Write the metadata in the layout map_saver produces.
+    pub fn to_yaml(&self) -> String {
+        return format!(
+            "image: {}\nresolution: {}\norigin: [{}, {}, {}]\nnegate: {}\noccupied_thresh: {}\nfree_thresh: {}\n",
+            self.image,
+            self.resolution,
+            self.origin[0],
+            self.origin[1],
+            self.origin[2],
+            self.negate as u8,
+            self.occupied_thresh,
+            self.free_thresh,
+        );
+    }
+
+    /// Read a map_server YAML file. Only the flat `key: value` subset map_server uses is
+    /// understood; unknown keys such as `mode` are ignored.
+    pub fn parse_yaml(text: &str) -> Result<RosMapMetadata, RosMapError> {
+        let defaults = RosMapMetadata::default();
+        let mut image: Option<String> = None;
+        let mut resolution: Option<f64> = None;
+        let mut origin: Option<[f64; 3]> = None;
+        let mut negate = defaults.negate;
+        let mut occupied_thresh = defaults.occupied_thresh;
+        let mut free_thresh = defaults.free_thresh;
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = RosMapError::InvalidYaml { line: line_idx + 1 };
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(invalid);
+            };
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            match key.trim() {
+                "image" => image = Some(value.to_string()),
+                "resolution" => resolution = Some(value.parse().map_err(|_| invalid)?),
+                "origin" => {
+                    let numbers: Vec<f64> = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|number| number.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| RosMapError::InvalidYaml { line: line_idx + 1 })?;
+                    let [x, y, yaw] = numbers[..] else {
+                        return Err(RosMapError::InvalidYaml { line: line_idx + 1 });
+                    };
+                    origin = Some([x, y, yaw]);
+                }
+                "negate" => {
+                    negate = match value {
+                        "0" | "false" => false,
+                        "1" | "true" => true,
+                        _ => {
+                            return Err(invalid);
+                        }
+                    }
+                }
+                "occupied_thresh" => occupied_thresh = value.parse().map_err(|_| invalid)?,
+                "free_thresh" => free_thresh = value.parse().map_err(|_| invalid)?,
+                _ => {}
+            }
+        }
+        return Ok(RosMapMetadata {
+            image: image.ok_or(RosMapError::MissingKey { key: "image" })?,
+            resolution: resolution.ok_or(RosMapError::MissingKey { key: "resolution" })?,
+            origin: origin.ok_or(RosMapError::MissingKey { key: "origin" })?,
+            negate,
+            occupied_thresh,
+            free_thresh,
+        });
+    }
+}
+
+/// Pull the next whitespace-separated header token out of a PGM, skipping `#` comments.
+fn next_pgm_token(bytes: &[u8], cursor: &mut usize) -> Option<String> {
+    loop {
+        while *cursor < bytes.len() && bytes[*cursor].is_ascii_whitespace() {
+            *cursor += 1;
+        }
+        if *cursor < bytes.len() && bytes[*cursor] == b'#' {
+            while *cursor < bytes.len() && bytes[*cursor] != b'\n' {
+                *cursor += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    let start = *cursor;
+    while *cursor < bytes.len() && !bytes[*cursor].is_ascii_whitespace() {
+        *cursor += 1;
+    }
+    if start == *cursor {
+        return None;
+    }
+    return Some(String::from_utf8_lossy(&bytes[start..*cursor]).into_owned());
+}
+
+impl Map {
+    /// Export the terrain as a binary PGM. Obstacles and junk are occupied, everything else
+    /// (including entities and the goal) is free. Image rows match the rows of
+    /// `generate_display_string`.
+    pub fn to_ros_pgm(&self) -> Option<Vec<u8>> {
+        let map = self.map.as_ref()?;
+        let mut pgm = format!(
+            "P5\n# Exported from Flatland\n{} {}\n255\n",
+            self.dimx, self.dimy
+        )
+        .into_bytes();
+        for colidx in 0..self.dimy {
+            for rowidx in 0..self.dimx {
+                pgm.push(match map[rowidx][colidx] {
+                    MapItem::Obstacle | MapItem::Junk => OCCUPIED_PIXEL,
+                    _ => FREE_PIXEL,
+                });
+            }
+        }
+        return Some(pgm);
+    }
+
+    /// Import an occupancy grid from its PGM image (binary `P5` or plain `P2`) and YAML
+    /// metadata. Cells between the free and occupied thresholds are unknown, and become
+    /// obstacles since the hero can't plan through them.
+    pub fn from_ros_map(pgm: &[u8], metadata: &RosMapMetadata) -> Result<Map, RosMapError> {
+        let mut cursor = 0;
+        let mut header: Vec<String> = Vec::new();
+        for _ in 0..4 {
+            header.push(next_pgm_token(pgm, &mut cursor).ok_or(RosMapError::InvalidImage)?);
+        }
+        let is_binary = match header[0].as_str() {
+            "P5" => true,
+            "P2" => false,
+            _ => {
+                return Err(RosMapError::InvalidImage);
+            }
+        };
+        let (Ok(dimx), Ok(dimy), Ok(maxval)) = (
+            header[1].parse::<usize>(),
+            header[2].parse::<usize>(),
+            header[3].parse::<u32>(),
+        ) else {
+            return Err(RosMapError::InvalidImage);
+        };
+        if dimx == 0 || dimy == 0 || maxval == 0 || maxval > u16::MAX as u32 {
+            return Err(RosMapError::InvalidImage);
+        }
+
+        let mut pixels: Vec<u32> = Vec::with_capacity(dimx * dimy);
+        if is_binary {
+            // Exactly one whitespace byte separates the header from the raster.
+            cursor += 1;
+            let bytes_per_pixel = if maxval > u8::MAX as u32 { 2 } else { 1 };
+            let raster = pgm.get(cursor..).unwrap_or(&[]);
+            if raster.len() < dimx * dimy * bytes_per_pixel {
+                return Err(RosMapError::TruncatedImage);
+            }
+            for pixel in raster.chunks(bytes_per_pixel).take(dimx * dimy) {
+                pixels.push(
+                    pixel
+                        .iter()
+                        .fold(0, |value, byte| value * 256 + *byte as u32),
+                );
+            }
+        } else {
+            while pixels.len() < dimx * dimy {
+                let token = next_pgm_token(pgm, &mut cursor).ok_or(RosMapError::TruncatedImage)?;
+                pixels.push(token.parse().map_err(|_| RosMapError::InvalidImage)?);
+            }
+        }
+
+        let mut map = Map::new(dimx, dimy);
+        let mut grid: MapGrid = map.filled_grid(MapItem::Obstacle);
+        for (idx, value) in pixels.iter().enumerate() {
+            let brightness = (*value).min(maxval) as f64 / maxval as f64;
+            let occupancy = if metadata.negate {
+                brightness
+            } else {
+                1. - brightness
+            };
+            if occupancy < metadata.free_thresh {
+                grid[idx % dimx][idx / dimx] = MapItem::Empty;
+            }
+        }
+        map.map = Some(grid);
+        return Ok(map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The terrain an export keeps: obstacles and junk block, everything else is free.
+    fn terrain(map: &Map) -> MapGrid {
+        let mut grid = map.map.clone().unwrap();
+        for item in grid.iter_mut().flatten() {
+            *item = match item {
+                MapItem::Obstacle | MapItem::Junk => MapItem::Obstacle,
+                _ => MapItem::Empty,
+            };
+        }
+        return grid;
+    }
+
+    #[test]
+    fn pgm_export_round_trips_terrain() {
+        let map = Map::from_text("#..H\n.x.E\nG..#").unwrap();
+        let pgm = map.to_ros_pgm().unwrap();
+        let imported = Map::from_ros_map(&pgm, &RosMapMetadata::default()).unwrap();
+        assert_eq!([imported.dimx(), imported.dimy()], [4, 3]);
+        assert_eq!(imported.map.unwrap(), terrain(&map));
+    }
+
+    #[test]
+    fn first_image_row_is_the_top_map_row() {
+        let map = Map::from_text(".#\n..").unwrap();
+        let pgm = map.to_ros_pgm().unwrap();
+        assert_eq!(
+            pgm[pgm.len() - 4..],
+            [FREE_PIXEL, OCCUPIED_PIXEL, FREE_PIXEL, FREE_PIXEL]
+        );
+    }
+
+    #[test]
+    fn yaml_round_trips() {
+        let metadata = RosMapMetadata {
+            image: "lab.pgm".to_string(),
+            resolution: 0.1,
+            origin: [-2.5, 1., 0.5],
+            negate: true,
+            occupied_thresh: 0.7,
+            free_thresh: 0.2,
+        };
+        assert_eq!(
+            RosMapMetadata::parse_yaml(&metadata.to_yaml()),
+            Ok(metadata)
+        );
+        assert_eq!(
+            RosMapMetadata::parse_yaml("image: a.pgm\nresolution: 0.05\n"),
+            Err(RosMapError::MissingKey { key: "origin" })
+        );
+    }
+
+    #[test]
+    fn thresholds_and_negation_decide_free_cells() {
+        // One row: black, dark grey, mid grey, light grey, white.
+        let pgm = b"P2\n5 1\n255\n0 40 128 220 255\n";
+        let metadata = RosMapMetadata::parse_yaml(
+            "image: lab.pgm\nresolution: 0.05\norigin: [0.0, 0.0, 0.0]\nnegate: 0\n",
+        )
+        .unwrap();
+        let imported = Map::from_ros_map(pgm, &metadata).unwrap().map.unwrap();
+        let row: Vec<MapItem> = (0..5).map(|pos_x| imported[pos_x][0]).collect();
+        // Occupancy is 1 - brightness; only cells under free_thresh (0.196) are free, and
+        // unknown cells between the thresholds are obstacles too.
+        assert_eq!(
+            row,
+            [
+                MapItem::Obstacle,
+                MapItem::Obstacle,
+                MapItem::Obstacle,
+                MapItem::Empty,
+                MapItem::Empty
+            ]
+        );
+
+        let negated = RosMapMetadata::parse_yaml(
+            "image: lab.pgm\nresolution: 0.05\norigin: [0.0, 0.0, 0.0]\nnegate: 1\n",
+        )
+        .unwrap();
+        assert!(negated.negate);
+        let imported = Map::from_ros_map(pgm, &negated).unwrap().map.unwrap();
+        let row: Vec<MapItem> = (0..5).map(|pos_x| imported[pos_x][0]).collect();
+        assert_eq!(
+            row,
+            [
+                MapItem::Empty,
+                MapItem::Empty,
+                MapItem::Obstacle,
+                MapItem::Obstacle,
+                MapItem::Obstacle
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_and_malformed_images_are_rejected() {
+        let metadata = RosMapMetadata::default();
+        assert_eq!(
+            Map::from_ros_map(b"P5\n2 2\n255\n\x00\x00", &metadata).err(),
+            Some(RosMapError::TruncatedImage)
+        );
+        assert_eq!(
+            Map::from_ros_map(b"P6\n2 2\n255\n", &metadata).err(),
+            Some(RosMapError::InvalidImage)
+        );
+    }
+}
@@ -0,0 +1,418 @@
+// Mirror and rotational symmetry for generated maps
+
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::map::{
+    GoalPlacement, MAX_HERO_PLACEMENT_ATTEMPTS, Map, MapGenerationError, MapGrid, MapItem,
+    PlacementOptions,
+};
+
+/// Symmetry imposed on a generated map, so planners can be compared fairly from mirrored
+/// starting positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapSymmetry {
+    /// No symmetry.
+    None,
+    /// The right half mirrors the left half.
+    MirrorLeftRight,
+    /// The bottom half mirrors the top half.
+    MirrorTopBottom,
+    /// The map looks the same after a half turn.
+    Rotate180,
+    /// The map looks the same after a quarter turn. Needs a square map.
+    Rotate90,
+}
+
+/// Where the hero and goal go on a symmetric map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymmetricSpawns {
+    /// The goal is the mirror image of the hero, so the route there is the same either way.
+    SwapHeroAndGoal,
+    /// The goal sits on a cell the symmetry leaves in place (on the mirror axis or at the
+    /// centre), leaving the hero's mirror image free for a second hero. Needs an odd width
+    /// (left-right), odd height (top-bottom) or both (rotations).
+    GoalOnAxis,
+}
+
+impl SymmetricSpawns {
+    /// Look up a spawn layout by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<SymmetricSpawns> {
+        match name {
+            "swap" => {
+                return Some(SymmetricSpawns::SwapHeroAndGoal);
+            }
+            "axis" => {
+                return Some(SymmetricSpawns::GoalOnAxis);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+impl MapSymmetry {
+    /// Look up a symmetry by the name used in the web interface.
+    pub fn from_name(name: &str) -> Option<MapSymmetry> {
+        match name {
+            "none" => {
+                return Some(MapSymmetry::None);
+            }
+            "left_right" => {
+                return Some(MapSymmetry::MirrorLeftRight);
+            }
+            "top_bottom" => {
+                return Some(MapSymmetry::MirrorTopBottom);
+            }
+            "rotate180" => {
+                return Some(MapSymmetry::Rotate180);
+            }
+            "rotate90" => {
+                return Some(MapSymmetry::Rotate90);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    /// Apply the symmetry's generating transform once.
+    fn image(&self, [pos_x, pos_y]: [usize; 2], [dimx, dimy]: [usize; 2]) -> [usize; 2] {
+        match self {
+            MapSymmetry::None => {
+                return [pos_x, pos_y];
+            }
+            MapSymmetry::MirrorLeftRight => {
+                return [dimx - 1 - pos_x, pos_y];
+            }
+            MapSymmetry::MirrorTopBottom => {
+                return [pos_x, dimy - 1 - pos_y];
+            }
+            MapSymmetry::Rotate180 => {
+                return [dimx - 1 - pos_x, dimy - 1 - pos_y];
+            }
+            MapSymmetry::Rotate90 => {
+                return [dimy - 1 - pos_y, pos_x];
+            }
+        }
+    }
+
+    /// The transform that swaps the hero and goal. Every symmetry except the quarter turn is
+    /// its own inverse; for that one the half turn is used.
+    fn swap_image(&self, position: [usize; 2], dims: [usize; 2]) -> [usize; 2] {
+        if *self == MapSymmetry::Rotate90 {
+            return MapSymmetry::Rotate180.image(position, dims);
+        }
+        return self.image(position, dims);
+    }
+
+    /// Every cell `position` is mapped to by repeating the symmetry, starting with itself.
+    fn orbit(&self, position: [usize; 2], dims: [usize; 2]) -> Vec<[usize; 2]> {
+        let mut orbit = vec![position];
+        let mut next = self.image(position, dims);
+        while next != position {
+            orbit.push(next);
+            next = self.image(next, dims);
+        }
+        return orbit;
+    }
+}
+
+impl Map {
+    fn check_symmetry(&self, symmetry: MapSymmetry) -> Result<(), MapGenerationError> {
+        if symmetry == MapSymmetry::Rotate90 && self.dimx != self.dimy {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+        return Ok(());
+    }
+
+    /// Check the map's size suits the symmetry and spawn layout before anything is generated.
+    /// `GoalOnAxis` needs a cell the symmetry leaves in place, which an even side doesn't have.
+    pub(super) fn check_symmetric_spawns(
+        &self,
+        symmetry: MapSymmetry,
+        spawns: SymmetricSpawns,
+    ) -> Result<(), MapGenerationError> {
+        self.check_symmetry(symmetry)?;
+        if spawns != SymmetricSpawns::GoalOnAxis {
+            return Ok(());
+        }
+        let (needs_odd_x, needs_odd_y) = match symmetry {
+            MapSymmetry::None => (false, false),
+            MapSymmetry::MirrorLeftRight => (true, false),
+            MapSymmetry::MirrorTopBottom => (false, true),
+            MapSymmetry::Rotate180 | MapSymmetry::Rotate90 => (true, true),
+        };
+        if (needs_odd_x && self.dimx.is_multiple_of(2))
+            || (needs_odd_y && self.dimy.is_multiple_of(2))
+        {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+        return Ok(());
+    }
+
+    /// Make the obstacles symmetric. Normally every cell copies the first cell of its orbit,
+    /// so one half (or quarter) of the generated layout is mirrored onto the rest. With
+    /// `keep_openings`, an orbit is cleared if any of its cells is open instead, which keeps
+    /// corridors dug by `GoalConnectivity::Carve`.
+    pub(super) fn symmetrize_obstacles(
+        &self,
+        working_map: &mut MapGrid,
+        symmetry: MapSymmetry,
+        keep_openings: bool,
+    ) -> Result<(), MapGenerationError> {
+        self.check_symmetry(symmetry)?;
+        let dims = [self.dimx, self.dimy];
+        for pos_x in 0..self.dimx {
+            for pos_y in 0..self.dimy {
+                let orbit = symmetry.orbit([pos_x, pos_y], dims);
+                let is_obstacle = if keep_openings {
+                    orbit
+                        .iter()
+                        .all(|[o_x, o_y]| working_map[*o_x][*o_y] == MapItem::Obstacle)
+                } else {
+                    let [first_x, first_y] = *orbit.iter().min().unwrap();
+                    working_map[first_x][first_y] == MapItem::Obstacle
+                };
+                if is_obstacle {
+                    working_map[pos_x][pos_y] = MapItem::Obstacle;
+                } else if working_map[pos_x][pos_y] == MapItem::Obstacle {
+                    working_map[pos_x][pos_y] = MapItem::Empty;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Symmetric replacement for `add_hero_and_goal_to_map`. The path distance constraint
+    /// still applies, and `GoalPlacement::Farthest` keeps the most distant of the sampled
+    /// hero positions.
+    pub(super) fn add_symmetric_hero_and_goal(
+        &self,
+        map_without_hero_or_goal: Option<MapGrid>,
+        symmetry: MapSymmetry,
+        spawns: SymmetricSpawns,
+        placement: &PlacementOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if map_without_hero_or_goal.is_none() {
+            return Err(MapGenerationError::PrevOpFailed);
+        }
+        let mut working_map = map_without_hero_or_goal.unwrap();
+        let dims = [self.dimx, self.dimy];
+        let unoccupied_positions = self.list_unoccupied_positions(&working_map);
+
+        // On-axis goal: the fixed point closest to the middle of the map, cleared if it was an
+        // obstacle (the axis only has a few cells, so they are often all blocked).
+        let centre = [(self.dimx - 1) as f32 / 2., (self.dimy - 1) as f32 / 2.];
+        let axis_goal = (0..self.dimx)
+            .flat_map(|pos_x| (0..self.dimy).map(move |pos_y| [pos_x, pos_y]))
+            .filter(|position| symmetry.image(*position, dims) == *position)
+            .min_by(|a, b| {
+                let dist = |[pos_x, pos_y]: [usize; 2]| {
+                    (pos_x as f32 - centre[0]).abs() + (pos_y as f32 - centre[1]).abs()
+                };
+                dist(*a).total_cmp(&dist(*b))
+            });
+
+        if let (SymmetricSpawns::GoalOnAxis, Some([goal_x, goal_y])) = (spawns, axis_goal) {
+            working_map[goal_x][goal_y] = MapItem::Empty;
+        }
+
+        let hero_candidates: Vec<[usize; 2]> = unoccupied_positions
+            .iter()
+            .filter(|position| symmetry.swap_image(**position, dims) != **position)
+            .copied()
+            .collect();
+        if hero_candidates.is_empty() {
+            return Err(MapGenerationError::RanOutOfSpace);
+        }
+
+        let mut best: Option<([usize; 2], [usize; 2], u32)> = None;
+        for _ in 0..MAX_HERO_PLACEMENT_ATTEMPTS {
+            let hero = hero_candidates[rng.random_range(0..hero_candidates.len())];
+            let goal = match spawns {
+                SymmetricSpawns::SwapHeroAndGoal => symmetry.swap_image(hero, dims),
+                SymmetricSpawns::GoalOnAxis => {
+                    let Some(goal) = axis_goal else {
+                        return Err(MapGenerationError::PlacementConstraintsUnsatisfiable);
+                    };
+                    goal
+                }
+            };
+            if goal == hero {
+                continue;
+            }
+            let distance = self.path_length(&working_map, hero, goal).unwrap_or(0);
+            if placement.min_hero_goal_path_distance > 0
+                && distance < placement.min_hero_goal_path_distance
+            {
+                continue;
+            }
+            if best.is_none_or(|(_, _, best_distance)| distance > best_distance) {
+                best = Some((hero, goal, distance));
+            }
+            if placement.goal_placement != GoalPlacement::Farthest {
+                break;
+            }
+        }
+
+        let Some((hero, goal, _)) = best else {
+            return Err(MapGenerationError::PlacementConstraintsUnsatisfiable);
+        };
+        working_map[hero[0]][hero[1]] = MapItem::HeroEntity;
+        working_map[goal[0]][goal[1]] = MapItem::Goal;
+        return Ok(working_map);
+    }
+
+    /// Copy every enemy onto the rest of its orbit. Enemies whose orbit would touch the hero
+    /// or goal, or bring a copy within `min_hero_enemy_distance` of the hero, are removed
+    /// along with all their copies.
+    pub(super) fn mirror_enemies(
+        &self,
+        working_map: &mut MapGrid,
+        symmetry: MapSymmetry,
+        placement: &PlacementOptions,
+    ) {
+        let dims = [self.dimx, self.dimy];
+        let hero = self.find_item(working_map, MapItem::HeroEntity);
+        let min_distance = placement.min_hero_enemy_distance;
+        for pos_x in 0..self.dimx {
+            for pos_y in 0..self.dimy {
+                if working_map[pos_x][pos_y] != MapItem::EnemyEntity {
+                    continue;
+                }
+                let orbit = symmetry.orbit([pos_x, pos_y], dims);
+                let allowed = orbit.iter().all(|[o_x, o_y]| {
+                    let near_hero = hero.is_some_and(|hero| {
+                        let [delta_x, delta_y] = self.displacement(hero, [*o_x, *o_y]);
+                        delta_x.unsigned_abs() + delta_y.unsigned_abs() < min_distance
+                    });
+                    let cell = working_map[*o_x][*o_y];
+                    return !near_hero && (cell == MapItem::Empty || cell == MapItem::EnemyEntity);
+                });
+                let item = if allowed {
+                    MapItem::EnemyEntity
+                } else {
+                    MapItem::Empty
+                };
+                for [o_x, o_y] in orbit {
+                    if working_map[o_x][o_y] == MapItem::Empty
+                        || working_map[o_x][o_y] == MapItem::EnemyEntity
+                    {
+                        working_map[o_x][o_y] = item;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of cells in a typical orbit, used to scale the enemy count so the mirrored map
+    /// ends up with about as many enemies as requested.
+    pub(super) fn symmetry_orbit_size(&self, symmetry: MapSymmetry) -> usize {
+        match symmetry {
+            MapSymmetry::None => {
+                return 1;
+            }
+            MapSymmetry::Rotate90 => {
+                return 4;
+            }
+            _ => {
+                return 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapGenerationOptions;
+    use rand::SeedableRng;
+
+    fn generate(
+        [dimx, dimy]: [usize; 2],
+        symmetry: MapSymmetry,
+    ) -> Result<MapGrid, MapGenerationError> {
+        let options = MapGenerationOptions {
+            symmetry,
+            symmetric_spawns: SymmetricSpawns::GoalOnAxis,
+            ..Default::default()
+        };
+        return Map::new(dimx, dimy).generate_map(&options, &mut StdRng::seed_from_u64(0));
+    }
+
+    #[test]
+    fn goal_on_axis_needs_odd_sides_across_the_axis() {
+        let cases = [
+            (MapSymmetry::MirrorLeftRight, [32, 31], false),
+            (MapSymmetry::MirrorLeftRight, [31, 32], true),
+            (MapSymmetry::MirrorTopBottom, [31, 32], false),
+            (MapSymmetry::MirrorTopBottom, [32, 31], true),
+            (MapSymmetry::Rotate180, [31, 32], false),
+            (MapSymmetry::Rotate180, [31, 33], true),
+            (MapSymmetry::Rotate90, [32, 32], false),
+            (MapSymmetry::Rotate90, [31, 31], true),
+        ];
+        for (symmetry, dims, accepted) in cases {
+            let result = generate(dims, symmetry);
+            if accepted {
+                let grid = result.unwrap();
+                let goal = Map::new(dims[0], dims[1]).find_item(&grid, MapItem::Goal);
+                assert_eq!(goal.map(|goal| symmetry.image(goal, dims)), goal);
+            } else {
+                assert!(
+                    matches!(
+                        result.err(),
+                        Some(MapGenerationError::InvalidGeneratorParameters)
+                    ),
+                    "{symmetry:?} on {dims:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn obstacles_and_enemies_match_their_images() {
+        let dims = [32, 32];
+        let game_map = Map::new(dims[0], dims[1]);
+        for symmetry in [
+            MapSymmetry::MirrorLeftRight,
+            MapSymmetry::MirrorTopBottom,
+            MapSymmetry::Rotate180,
+            MapSymmetry::Rotate90,
+        ] {
+            let options = MapGenerationOptions {
+                symmetry,
+                ..Default::default()
+            };
+            for seed in 0..5 {
+                let grid = game_map
+                    .generate_map(&options, &mut StdRng::seed_from_u64(seed))
+                    .unwrap();
+                let mut enemies = 0;
+                for pos_x in 0..dims[0] {
+                    for pos_y in 0..dims[1] {
+                        let item = grid[pos_x][pos_y];
+                        let [image_x, image_y] = symmetry.image([pos_x, pos_y], dims);
+                        let image_item = grid[image_x][image_y];
+                        if matches!(item, MapItem::Obstacle | MapItem::EnemyEntity)
+                            || matches!(image_item, MapItem::Obstacle | MapItem::EnemyEntity)
+                        {
+                            assert_eq!(item, image_item, "{symmetry:?}, seed {seed}");
+                        }
+                        if item == MapItem::EnemyEntity {
+                            enemies += 1;
+                        }
+                    }
+                }
+                assert!(enemies > 0, "{symmetry:?}, seed {seed}");
+
+                let hero = game_map.find_item(&grid, MapItem::HeroEntity).unwrap();
+                let goal = game_map.find_item(&grid, MapItem::Goal).unwrap();
+                assert_eq!(symmetry.swap_image(hero, dims), goal);
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+// Plain-text map import and export
+
+use crate::map::{Map, MapGrid, MapItem};
+
+/// One-character-per-cell legend for hand-written levels.
+const ASCII_LEGEND: [(MapItem, char); 6] = [
+    (MapItem::Empty, '.'),
+    (MapItem::Obstacle, '#'),
+    (MapItem::HeroEntity, 'H'),
+    (MapItem::EnemyEntity, 'E'),
+    (MapItem::Goal, 'G'),
+    (MapItem::Junk, 'x'),
+];
+
+/// Box-drawing characters `Game::print_game_state` puts around the map.
+const BORDER_CHARS: [char; 6] = [
+    '\u{250C}', '\u{2500}', '\u{2510}', '\u{2502}', '\u{2514}', '\u{2518}',
+];
+
+#[derive(Debug, PartialEq)]
+pub enum MapParseError {
+    /// The text held no map rows.
+    Empty,
+    /// A cell didn't match any glyph. Lines and columns count from 1, columns in characters.
+    UnknownGlyph {
+        line: usize,
+        column: usize,
+        glyph: char,
+    },
+    /// The map needs exactly one of `item` but had `count`.
+    WrongEntityCount { item: MapItem, count: usize },
+}
+
+impl Map {
+    /// Parse a map from text, in either of two formats:
+    ///
+    /// * The Unicode format written by `generate_display_string`, two characters per cell,
+    ///   optionally inside the box `Game::print_game_state` draws around it.
+    /// * A one-character-per-cell ASCII format: `.` empty, `#` obstacle, `H` hero,
+    ///   `E` enemy, `G` goal and `x` junk, as written by `generate_ascii_string`.
+    ///
+    /// Each line is one row (y) and each cell along it one column (x). Short lines are padded
+    /// with empty cells, so editors that strip trailing whitespace don't break a level.
+    /// Parsing the output of either writer gives back the same grid.
+    ///
+    /// The format is taken from the box, if there is one, or else from the first glyph that
+    /// only one of the formats uses. Text made only of spaces is read as the Unicode format,
+    /// since the ASCII writer never writes spaces.
+    pub fn from_text(text: &str) -> Result<Map, MapParseError> {
+        let is_unicode = text.trim_start().starts_with(BORDER_CHARS[0])
+            || text
+                .chars()
+                .find_map(|glyph| {
+                    if ASCII_LEGEND.iter().any(|(_, mapped)| *mapped == glyph) {
+                        return Some(false);
+                    }
+                    if glyph != ' '
+                        && MapItem::generate_default_unicode_mappings()
+                            .values()
+                            .any(|mapped| *mapped == glyph)
+                    {
+                        return Some(true);
+                    }
+                    return None;
+                })
+                .unwrap_or(true);
+
+        let mut rows: Vec<(usize, Vec<char>)> = Vec::new();
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('\u{250C}') || line.starts_with('\u{2514}') {
+                continue;
+            }
+            let line = line
+                .strip_prefix('\u{2502}')
+                .map(|inner| inner.strip_suffix('\u{2502}').unwrap_or(inner))
+                .unwrap_or(line);
+            if !is_unicode && line.trim().is_empty() {
+                continue;
+            }
+            rows.push((line_idx + 1, line.chars().collect()));
+        }
+        // Trailing empty lines are just the end of the file, not rows of empty cells.
+        while rows.last().is_some_and(|(_, row)| row.is_empty()) {
+            rows.pop();
+        }
+
+        let chars_per_cell = if is_unicode { 2 } else { 1 };
+        let dimy = rows.len();
+        let dimx = rows
+            .iter()
+            .map(|(_, row)| row.len().div_ceil(chars_per_cell))
+            .max()
+            .unwrap_or(0);
+        if dimx == 0 || dimy == 0 {
+            return Err(MapParseError::Empty);
+        }
+
+        let mut map = Map::new(dimx, dimy);
+        let mut grid: MapGrid = map.filled_grid(MapItem::Empty);
+        for (pos_y, (line_number, row)) in rows.iter().enumerate() {
+            for (pos_x, cell) in row.chunks(chars_per_cell).enumerate() {
+                let glyph = cell[0];
+                let item = if is_unicode {
+                    map.unicode_mappings
+                        .iter()
+                        .find(|(_, mapped)| **mapped == glyph)
+                        .map(|(item, _)| *item)
+                } else {
+                    // Spaces are accepted as empty cells, as in the Unicode format.
+                    let glyph = if glyph == ' ' { '.' } else { glyph };
+                    ASCII_LEGEND
+                        .iter()
+                        .find(|(_, mapped)| *mapped == glyph)
+                        .map(|(item, _)| *item)
+                };
+                let Some(item) = item else {
+                    return Err(MapParseError::UnknownGlyph {
+                        line: *line_number,
+                        column: pos_x * chars_per_cell + 1,
+                        glyph,
+                    });
+                };
+                grid[pos_x][pos_y] = item;
+            }
+        }
+        map.map = Some(grid);
+        return Ok(map);
+    }
+
+    /// Generate a string that contains the map in the one-character-per-cell ASCII format.
+    pub fn generate_ascii_string(&self) -> Option<String> {
+        let map = self.map.as_ref()?;
+        let mut ascii_string = String::new();
+        for colidx in 0..self.dimy {
+            for rowidx in 0..self.dimx {
+                let (_, glyph) = ASCII_LEGEND
+                    .iter()
+                    .find(|(item, _)| *item == map[rowidx][colidx])
+                    .expect("Every map item should have an ASCII glyph");
+                ascii_string.push(*glyph);
+            }
+            ascii_string.push('\n');
+        }
+        return Some(ascii_string);
+    }
+
+    /// Check that the map holds exactly one hero and exactly one goal, as a playable level must.
+    pub fn validate_level(&self) -> Result<(), MapParseError> {
+        let Some(map) = self.map.as_ref() else {
+            return Err(MapParseError::Empty);
+        };
+        for item in [MapItem::HeroEntity, MapItem::Goal] {
+            let count = map.iter().flatten().filter(|cell| **cell == item).count();
+            if count != 1 {
+                return Err(MapParseError::WrongEntityCount { item, count });
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapGenerationOptions;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// A generated map with some junk on it, so every kind of cell shows up.
+    fn sample_map(seed: u64) -> Map {
+        let mut game_map = Map::new(24, 16);
+        let mut grid = game_map
+            .generate_map(
+                &MapGenerationOptions::default(),
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+        let empty_cells = game_map.list_unoccupied_positions(&grid);
+        for [pos_x, pos_y] in empty_cells.into_iter().step_by(17) {
+            grid[pos_x][pos_y] = MapItem::Junk;
+        }
+        game_map.map = Some(grid);
+        return game_map;
+    }
+
+    #[test]
+    fn ascii_string_round_trips() {
+        for seed in 0..5 {
+            let game_map = sample_map(seed);
+            let text = game_map.generate_ascii_string().unwrap();
+            let parsed = Map::from_text(&text).unwrap();
+            assert_eq!([parsed.dimx(), parsed.dimy()], [24, 16]);
+            assert_eq!(parsed.map, game_map.map);
+        }
+    }
+
+    #[test]
+    fn display_string_round_trips() {
+        for seed in 0..5 {
+            let game_map = sample_map(seed);
+            let text = game_map.generate_display_string().unwrap();
+            assert_eq!(Map::from_text(&text).unwrap().map, game_map.map);
+        }
+    }
+
+    #[test]
+    fn empty_unicode_map_round_trips() {
+        let mut game_map = Map::new(5, 3);
+        game_map.map = Some(game_map.filled_grid(MapItem::Empty));
+        let text = game_map.generate_display_string().unwrap();
+        let parsed = Map::from_text(&text).unwrap();
+        assert_eq!([parsed.dimx(), parsed.dimy()], [5, 3]);
+        assert_eq!(parsed.map, game_map.map);
+
+        let boxed = format!(
+            "\u{250C}{}\u{2510}\n\u{2502}{}\u{2502}\n\u{2514}{}\u{2518}\n",
+            "\u{2500}".repeat(4),
+            "    ",
+            "\u{2500}".repeat(4)
+        );
+        let parsed = Map::from_text(&boxed).unwrap();
+        assert_eq!([parsed.dimx(), parsed.dimy()], [2, 1]);
+    }
+
+    #[test]
+    fn format_comes_from_the_first_distinguishing_glyph() {
+        // Spaces before the first ASCII glyph are empty cells, one per character.
+        let parsed = Map::from_text("  #\n.H.G").unwrap();
+        assert_eq!([parsed.dimx(), parsed.dimy()], [4, 2]);
+        assert_eq!(parsed.map.as_ref().unwrap()[2][0], MapItem::Obstacle);
+        assert_eq!(
+            Map::from_text("#\u{2588}").err(),
+            Some(MapParseError::UnknownGlyph {
+                line: 1,
+                column: 2,
+                glyph: '\u{2588}'
+            })
+        );
+    }
+}
@@ -0,0 +1,450 @@
+// Tiled map editor (JSON map format) import/export
+// Format reference: https://doc.mapeditor.org/en/stable/reference/json-map-format/
+//
+// Terrain is one tile layer using the Flatland tileset: local tile 0 is empty, 1 is an
+// obstacle and 2 is junk. Hero, goal and enemy spawns are objects in an object layer whose
+// class (or type, before Tiled 1.9) is `hero`, `goal` or `enemy`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::{Map, MapGrid, MapItem};
+
+/// Tiled sets the top bits of a GID to flag flipped or rotated tiles.
+const GID_FLAG_MASK: u32 = 0xF000_0000;
+/// Terrain tiles in the order they appear in `TILESET_IMAGE`.
+const TERRAIN_TILES: [MapItem; 3] = [MapItem::Empty, MapItem::Obstacle, MapItem::Junk];
+/// Tileset image shipped next to the example levels.
+const TILESET_IMAGE: &str = "flatland_tiles.png";
+const TILE_SIZE_PX: u32 = 16;
+const TERRAIN_LAYER_NAME: &str = "terrain";
+const SPAWN_LAYER_NAME: &str = "spawns";
+
+#[derive(Debug, PartialEq)]
+pub enum TiledError {
+    /// The file isn't valid Tiled JSON.
+    InvalidJson(String),
+    /// Only finite, orthogonal maps are supported.
+    UnsupportedMap,
+    /// There was no tile layer, or it didn't cover the whole map.
+    MissingTerrain,
+    /// A tile outside the Flatland tileset.
+    UnknownTile { gid: u32 },
+    /// A spawn object whose class isn't `hero`, `goal` or `enemy`.
+    UnknownObject { class: String },
+    /// A spawn object outside the map or on top of an obstacle.
+    BlockedSpawn { position: [i64; 2] },
+    /// The terrain layer is stored base64-encoded or compressed rather than as a plain list
+    /// of tiles. Set the layer format to CSV in Tiled's map properties.
+    UnsupportedEncoding {
+        encoding: String,
+        compression: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct TiledMap {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    version: String,
+    orientation: String,
+    #[serde(default)]
+    renderorder: String,
+    width: usize,
+    height: usize,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    nextlayerid: u32,
+    #[serde(default)]
+    nextobjectid: u32,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TiledLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    width: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    height: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<TiledLayerData>,
+    /// `csv` (the default) or `base64`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    /// `zlib`, `gzip` or `zstd` for compressed base64 data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    objects: Option<Vec<TiledObject>>,
+    #[serde(default)]
+    x: i64,
+    #[serde(default)]
+    y: i64,
+    #[serde(default = "default_opacity")]
+    opacity: f64,
+    #[serde(default = "default_visible")]
+    visible: bool,
+}
+
+/// Tile layer data: a list of GIDs in CSV maps, or a base64 string otherwise.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum TiledLayerData {
+    Tiles(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct TiledObject {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    /// Called `class` since Tiled 1.9.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    class: String,
+    /// Called `type` up to Tiled 1.8.
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
+    kind: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default)]
+    point: bool,
+    /// Set on tile objects, which Tiled anchors at their bottom-left corner instead of the
+    /// top-left.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gid: Option<u32>,
+    #[serde(default = "default_visible")]
+    visible: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+}
+
+fn default_opacity() -> f64 {
+    return 1.;
+}
+
+fn default_visible() -> bool {
+    return true;
+}
+
+impl Map {
+    /// Export the map as a Tiled JSON map (`.tmj`) that uses `flatland_tiles.png` as its
+    /// tileset image.
+    pub fn to_tiled_json(&self) -> Option<String> {
+        let map = self.map.as_ref()?;
+        let mut data: Vec<u32> = Vec::with_capacity(self.dimx * self.dimy);
+        let mut objects: Vec<TiledObject> = Vec::new();
+        for colidx in 0..self.dimy {
+            for rowidx in 0..self.dimx {
+                let (tile, class) = match map[rowidx][colidx] {
+                    MapItem::Obstacle => (MapItem::Obstacle, None),
+                    MapItem::Junk => (MapItem::Junk, None),
+                    MapItem::HeroEntity => (MapItem::Empty, Some("hero")),
+                    MapItem::Goal => (MapItem::Empty, Some("goal")),
+                    MapItem::EnemyEntity => (MapItem::Empty, Some("enemy")),
+                    MapItem::Empty => (MapItem::Empty, None),
+                };
+                let local_id = TERRAIN_TILES.iter().position(|item| *item == tile).unwrap();
+                data.push(local_id as u32 + 1);
+                if let Some(class) = class {
+                    objects.push(TiledObject {
+                        id: objects.len() as u32 + 1,
+                        name: String::new(),
+                        class: class.to_string(),
+                        kind: String::new(),
+                        x: ((rowidx as u32 * TILE_SIZE_PX) + TILE_SIZE_PX / 2) as f64,
+                        y: ((colidx as u32 * TILE_SIZE_PX) + TILE_SIZE_PX / 2) as f64,
+                        width: 0.,
+                        height: 0.,
+                        rotation: 0.,
+                        point: true,
+                        gid: None,
+                        visible: true,
+                    });
+                }
+            }
+        }
+        let next_object_id = objects.len() as u32 + 1;
+        let tiled_map = TiledMap {
+            kind: "map".to_string(),
+            version: "1.10".to_string(),
+            orientation: "orthogonal".to_string(),
+            renderorder: "right-down".to_string(),
+            width: self.dimx,
+            height: self.dimy,
+            tilewidth: TILE_SIZE_PX,
+            tileheight: TILE_SIZE_PX,
+            infinite: false,
+            nextlayerid: 3,
+            nextobjectid: next_object_id,
+            layers: vec![
+                TiledLayer {
+                    kind: "tilelayer".to_string(),
+                    id: 1,
+                    name: TERRAIN_LAYER_NAME.to_string(),
+                    width: Some(self.dimx),
+                    height: Some(self.dimy),
+                    data: Some(TiledLayerData::Tiles(data)),
+                    encoding: None,
+                    compression: None,
+                    objects: None,
+                    x: 0,
+                    y: 0,
+                    opacity: 1.,
+                    visible: true,
+                },
+                TiledLayer {
+                    kind: "objectgroup".to_string(),
+                    id: 2,
+                    name: SPAWN_LAYER_NAME.to_string(),
+                    width: None,
+                    height: None,
+                    data: None,
+                    encoding: None,
+                    compression: None,
+                    objects: Some(objects),
+                    x: 0,
+                    y: 0,
+                    opacity: 1.,
+                    visible: true,
+                },
+            ],
+            tilesets: vec![TiledTileset {
+                firstgid: 1,
+                name: "flatland".to_string(),
+                image: TILESET_IMAGE.to_string(),
+                imagewidth: TILE_SIZE_PX * TERRAIN_TILES.len() as u32,
+                imageheight: TILE_SIZE_PX,
+                tilewidth: TILE_SIZE_PX,
+                tileheight: TILE_SIZE_PX,
+                tilecount: TERRAIN_TILES.len() as u32,
+                columns: TERRAIN_TILES.len() as u32,
+                margin: 0,
+                spacing: 0,
+            }],
+        };
+        return serde_json::to_string_pretty(&tiled_map).ok();
+    }
+
+    /// Load a map from Tiled JSON. The first tile layer is the terrain and every object layer
+    /// is searched for spawns; each object lands on the cell under its centre.
+    pub fn from_tiled_json(text: &str) -> Result<Map, TiledError> {
+        let tiled_map: TiledMap = serde_json::from_str(text)
+            .map_err(|error| TiledError::InvalidJson(error.to_string()))?;
+        if tiled_map.orientation != "orthogonal"
+            || tiled_map.infinite
+            || tiled_map.width == 0
+            || tiled_map.height == 0
+            || tiled_map.tilewidth == 0
+            || tiled_map.tileheight == 0
+        {
+            return Err(TiledError::UnsupportedMap);
+        }
+        let Some(terrain_layer) = tiled_map
+            .layers
+            .iter()
+            .find(|layer| layer.kind == "tilelayer")
+        else {
+            return Err(TiledError::MissingTerrain);
+        };
+        let data = match &terrain_layer.data {
+            Some(TiledLayerData::Tiles(data))
+                if terrain_layer.encoding.as_deref().unwrap_or("csv") == "csv" =>
+            {
+                data
+            }
+            Some(_) => {
+                return Err(TiledError::UnsupportedEncoding {
+                    encoding: terrain_layer
+                        .encoding
+                        .clone()
+                        .unwrap_or_else(|| "base64".to_string()),
+                    compression: terrain_layer
+                        .compression
+                        .clone()
+                        .filter(|compression| !compression.is_empty()),
+                });
+            }
+            None => {
+                return Err(TiledError::MissingTerrain);
+            }
+        };
+        if data.len() != tiled_map.width * tiled_map.height {
+            return Err(TiledError::MissingTerrain);
+        }
+        let firstgid = tiled_map
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.firstgid)
+            .min()
+            .unwrap_or(1);
+
+        let mut map = Map::new(tiled_map.width, tiled_map.height);
+        let mut grid: MapGrid = map.filled_grid(MapItem::Empty);
+        for (idx, raw_gid) in data.iter().enumerate() {
+            let gid = raw_gid & !GID_FLAG_MASK;
+            if gid == 0 {
+                // No tile painted here.
+                continue;
+            }
+            let Some(item) = gid
+                .checked_sub(firstgid)
+                .and_then(|local_id| TERRAIN_TILES.get(local_id as usize))
+            else {
+                return Err(TiledError::UnknownTile { gid });
+            };
+            grid[idx % tiled_map.width][idx / tiled_map.width] = *item;
+        }
+
+        for layer in &tiled_map.layers {
+            let Some(objects) = layer.objects.as_ref() else {
+                continue;
+            };
+            for object in objects {
+                let class = if object.class.is_empty() {
+                    &object.kind
+                } else {
+                    &object.class
+                };
+                let item = match class.to_lowercase().as_str() {
+                    "hero" => MapItem::HeroEntity,
+                    "goal" => MapItem::Goal,
+                    "enemy" => MapItem::EnemyEntity,
+                    _ => {
+                        return Err(TiledError::UnknownObject {
+                            class: class.clone(),
+                        });
+                    }
+                };
+                // Tile objects hang up from their anchor, everything else hangs down.
+                let top = if object.gid.is_some() {
+                    let height = if object.height > 0. {
+                        object.height
+                    } else {
+                        tiled_map.tileheight as f64
+                    };
+                    object.y - height
+                } else {
+                    object.y
+                };
+                let position = [
+                    ((object.x + object.width / 2.) / tiled_map.tilewidth as f64).floor() as i64,
+                    ((top + object.height / 2.) / tiled_map.tileheight as f64).floor() as i64,
+                ];
+                if position[0] < 0
+                    || position[1] < 0
+                    || position[0] as usize >= tiled_map.width
+                    || position[1] as usize >= tiled_map.height
+                    || grid[position[0] as usize][position[1] as usize] != MapItem::Empty
+                {
+                    return Err(TiledError::BlockedSpawn { position });
+                }
+                grid[position[0] as usize][position[1] as usize] = item;
+            }
+        }
+        map.map = Some(grid);
+        return Ok(map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 map with an obstacle at [1, 0] and a spawn layer holding `objects`.
+    fn map_with_objects(objects: &str) -> String {
+        return format!(
+            r#"{{
+                "orientation": "orthogonal", "width": 3, "height": 2,
+                "tilewidth": 16, "tileheight": 16,
+                "layers": [
+                    {{"type": "tilelayer", "data": [1, 2, 1, 1, 1, 1]}},
+                    {{"type": "objectgroup", "objects": [{objects}]}}
+                ],
+                "tilesets": [{{"firstgid": 1}}]
+            }}"#
+        );
+    }
+
+    #[test]
+    fn tiled_export_round_trips() {
+        let map = Map::from_text("#..H.\n.x.E.\nG..#E").unwrap();
+        let imported = Map::from_tiled_json(&map.to_tiled_json().unwrap()).unwrap();
+        assert_eq!([imported.dimx(), imported.dimy()], [5, 3]);
+        assert_eq!(imported.map, map.map);
+    }
+
+    #[test]
+    fn example_level_loads() {
+        let level = Map::from_tiled_json(include_str!("../../levels/two_rooms.tmj")).unwrap();
+        assert!(level.validate_level().is_ok());
+    }
+
+    #[test]
+    fn tile_objects_are_anchored_at_their_bottom_left() {
+        // A 16x16 tile object drawn over cell [2, 0] has its anchor at the bottom of that cell;
+        // a rectangle over cell [0, 1] has its anchor at the top.
+        let text = map_with_objects(
+            r#"{"class": "hero", "gid": 1, "x": 32, "y": 16, "width": 16, "height": 16},
+               {"class": "goal", "x": 0, "y": 16, "width": 16, "height": 16}"#,
+        );
+        let grid = Map::from_tiled_json(&text).unwrap().map.unwrap();
+        assert_eq!(grid[2][0], MapItem::HeroEntity);
+        assert_eq!(grid[0][1], MapItem::Goal);
+    }
+
+    #[test]
+    fn encoded_layers_are_rejected() {
+        let text = map_with_objects("").replace(
+            r#""data": [1, 2, 1, 1, 1, 1]"#,
+            r#""data": "eJxjZGBgYAAAABAAAg==", "encoding": "base64", "compression": "zlib""#,
+        );
+        assert_eq!(
+            Map::from_tiled_json(&text).err(),
+            Some(TiledError::UnsupportedEncoding {
+                encoding: "base64".to_string(),
+                compression: Some("zlib".to_string()),
+            })
+        );
+    }
+}
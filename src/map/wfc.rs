@@ -0,0 +1,403 @@
+// Wave Function Collapse obstacle generator
+// Overlapping model: https://github.com/mxgmn/WaveFunctionCollapse
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::map::{
+    Map, MapGenerationError, MapGenerationOptions, MapGenerator, MapGrid, MapItem, MapParseError,
+};
+
+/// Example maps shipped in `wfc_examples/`.
+const BUILT_IN_EXAMPLES: [(&str, &str); 2] = [
+    ("ruins", include_str!("../../wfc_examples/ruins.txt")),
+    ("pillars", include_str!("../../wfc_examples/pillars.txt")),
+];
+
+/// Offsets to the right, down, left and up neighbor, in that order.
+const DIRECTIONS: [[i32; 2]; 4] = [[1, 0], [0, 1], [-1, 0], [0, -1]];
+
+fn opposite(direction: usize) -> usize {
+    return (direction + 2) % 4;
+}
+
+/// Settings and learned rules for the Wave Function Collapse generator.
+///
+/// Every `pattern_size` by `pattern_size` window of the example becomes a pattern, weighted by
+/// how often it appears. Two patterns may sit next to each other when they agree on the cells
+/// they overlap, so the output only contains local arrangements that occur in the example.
+/// Entities in the example count as empty cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WfcGenerator {
+    pub pattern_size: usize,
+    /// How many times to restart after running into a contradiction.
+    pub max_attempts: u32,
+    /// Learned patterns, each stored row by row with `true` for an obstacle.
+    patterns: Vec<Vec<bool>>,
+    weights: Vec<f64>,
+    /// `propagator[a][d]` lists the patterns allowed one step in direction `d` from `a`.
+    propagator: Vec<[Vec<usize>; 4]>,
+}
+
+impl Default for WfcGenerator {
+    /// Learn 3x3 patterns from the `ruins` example.
+    fn default() -> WfcGenerator {
+        return WfcGenerator::built_in("ruins", 3).expect("Built-in example should parse");
+    }
+}
+
+impl MapGenerator for WfcGenerator {
+    /// Synthesize an obstacle layout the size of `map`, restarting on contradictions.
+    fn generate(
+        &self,
+        map: &Map,
+        _options: &MapGenerationOptions,
+        rng: &mut StdRng,
+    ) -> Result<MapGrid, MapGenerationError> {
+        if self.patterns.is_empty()
+            || self.max_attempts == 0
+            || map.dimx() < self.pattern_size
+            || map.dimy() < self.pattern_size
+        {
+            return Err(MapGenerationError::InvalidGeneratorParameters);
+        }
+        for _ in 0..self.max_attempts {
+            if let Some(chosen) = self.run(map, rng) {
+                return Ok(self.render(map, &chosen));
+            }
+        }
+        return Err(MapGenerationError::WfcContradiction {
+            attempts: self.max_attempts,
+        });
+    }
+}
+
+impl WfcGenerator {
+    /// Learn from one of the examples in `wfc_examples/` by file name, without the extension.
+    pub fn built_in(name: &str, pattern_size: usize) -> Option<WfcGenerator> {
+        let (_, text) = BUILT_IN_EXAMPLES
+            .iter()
+            .find(|(example_name, _)| *example_name == name)?;
+        return WfcGenerator::from_example(text, pattern_size).ok();
+    }
+
+    /// Learn patterns from an example map in any format `Map::from_text` reads. Examples
+    /// smaller than the pattern size learn nothing, and `generate` reports
+    /// `InvalidGeneratorParameters`.
+    pub fn from_example(text: &str, pattern_size: usize) -> Result<WfcGenerator, MapParseError> {
+        let example = Map::from_text(text)?;
+        let grid = example.map.as_ref().expect("Parsed maps have a grid");
+        let mut patterns: Vec<Vec<bool>> = Vec::new();
+        let mut weights: Vec<f64> = Vec::new();
+        let mut pattern_index: HashMap<Vec<bool>, usize> = HashMap::new();
+        if pattern_size > 0 && example.dimx() >= pattern_size && example.dimy() >= pattern_size {
+            for origin_y in 0..=(example.dimy() - pattern_size) {
+                for origin_x in 0..=(example.dimx() - pattern_size) {
+                    let mut pattern: Vec<bool> = Vec::with_capacity(pattern_size * pattern_size);
+                    for offset_y in 0..pattern_size {
+                        for offset_x in 0..pattern_size {
+                            let item = grid[origin_x + offset_x][origin_y + offset_y];
+                            pattern.push(item == MapItem::Obstacle || item == MapItem::Junk);
+                        }
+                    }
+                    // Keep first-seen order so the same seed always gives the same map.
+                    if let Some(idx) = pattern_index.get(&pattern) {
+                        weights[*idx] += 1.;
+                    } else {
+                        pattern_index.insert(pattern.clone(), patterns.len());
+                        patterns.push(pattern);
+                        weights.push(1.);
+                    }
+                }
+            }
+        }
+
+        let mut propagator: Vec<[Vec<usize>; 4]> = Vec::with_capacity(patterns.len());
+        for first in &patterns {
+            let mut allowed: [Vec<usize>; 4] = Default::default();
+            for (direction, offset) in DIRECTIONS.iter().enumerate() {
+                for (idx, second) in patterns.iter().enumerate() {
+                    if patterns_agree(first, second, *offset, pattern_size) {
+                        allowed[direction].push(idx);
+                    }
+                }
+            }
+            propagator.push(allowed);
+        }
+
+        return Ok(WfcGenerator {
+            pattern_size,
+            max_attempts: 10,
+            patterns,
+            weights,
+            propagator,
+        });
+    }
+
+    /// Number of distinct patterns learned from the example.
+    pub fn pattern_count(&self) -> usize {
+        return self.patterns.len();
+    }
+
+    /// One collapse of the whole wave. Each wave cell is the top-left corner of a pattern, so
+    /// the wave is `pattern_size - 1` smaller than the map in each direction. Returns the
+    /// pattern chosen for every wave cell, or None on a contradiction.
+    fn run(&self, map: &Map, rng: &mut StdRng) -> Option<Vec<usize>> {
+        let wave = Wave::new(self, map.dimx(), map.dimy());
+        return wave.collapse(self, rng);
+    }
+
+    fn render(&self, map: &Map, chosen: &[usize]) -> MapGrid {
+        let mut working_map = map.filled_grid(MapItem::Empty);
+        let wave_dims = [
+            map.dimx() - self.pattern_size + 1,
+            map.dimy() - self.pattern_size + 1,
+        ];
+        for pos_x in 0..map.dimx() {
+            for pos_y in 0..map.dimy() {
+                // Cells past the last wave cell come from the last pattern that covers them.
+                let wave_x = pos_x.min(wave_dims[0] - 1);
+                let wave_y = pos_y.min(wave_dims[1] - 1);
+                let pattern = &self.patterns[chosen[wave_x * wave_dims[1] + wave_y]];
+                let [offset_x, offset_y] = [pos_x - wave_x, pos_y - wave_y];
+                if pattern[offset_y * self.pattern_size + offset_x] {
+                    working_map[pos_x][pos_y] = MapItem::Obstacle;
+                }
+            }
+        }
+        return working_map;
+    }
+}
+
+/// Whether `second`, shifted by `offset` from `first`, matches it wherever the two overlap.
+fn patterns_agree(first: &[bool], second: &[bool], offset: [i32; 2], size: usize) -> bool {
+    let size = size as i32;
+    for pos_y in 0..size {
+        for pos_x in 0..size {
+            let [other_x, other_y] = [pos_x - offset[0], pos_y - offset[1]];
+            if other_x < 0 || other_y < 0 || other_x >= size || other_y >= size {
+                continue;
+            }
+            if first[(pos_y * size + pos_x) as usize] != second[(other_y * size + other_x) as usize]
+            {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+/// Superposition of patterns at every wave cell, using the AC-4 support counts from the
+/// reference implementation so propagation stays linear in the number of bans.
+struct Wave {
+    dims: [usize; 2],
+    num_patterns: usize,
+    possible: Vec<bool>,
+    /// `supports[(cell * num_patterns + pattern) * 4 + d]` counts the patterns still possible
+    /// one step against direction `d` that allow `pattern` here.
+    supports: Vec<u32>,
+    remaining: Vec<usize>,
+    sum_weights: Vec<f64>,
+    sum_weight_log_weights: Vec<f64>,
+    pending_bans: Vec<(usize, usize)>,
+    /// Cells that lost patterns since the entropy heap was last updated.
+    touched: Vec<usize>,
+}
+
+impl Wave {
+    fn new(generator: &WfcGenerator, dimx: usize, dimy: usize) -> Wave {
+        let dims = [
+            dimx - generator.pattern_size + 1,
+            dimy - generator.pattern_size + 1,
+        ];
+        let num_cells = dims[0] * dims[1];
+        let num_patterns = generator.patterns.len();
+        let mut supports: Vec<u32> = Vec::with_capacity(num_cells * num_patterns * 4);
+        for _ in 0..num_cells {
+            for pattern in 0..num_patterns {
+                for direction in 0..4 {
+                    supports.push(generator.propagator[pattern][opposite(direction)].len() as u32);
+                }
+            }
+        }
+        let total_weight: f64 = generator.weights.iter().sum();
+        let total_weight_log_weight: f64 = generator
+            .weights
+            .iter()
+            .map(|weight| weight * weight.ln())
+            .sum();
+        return Wave {
+            dims,
+            num_patterns,
+            possible: vec![true; num_cells * num_patterns],
+            supports,
+            remaining: vec![num_patterns; num_cells],
+            sum_weights: vec![total_weight; num_cells],
+            sum_weight_log_weights: vec![total_weight_log_weight; num_cells],
+            pending_bans: Vec::new(),
+            touched: Vec::new(),
+        };
+    }
+
+    fn neighbor(&self, cell: usize, direction: usize) -> Option<usize> {
+        let [pos_x, pos_y] = [(cell / self.dims[1]) as i32, (cell % self.dims[1]) as i32];
+        let [n_x, n_y] = [
+            pos_x + DIRECTIONS[direction][0],
+            pos_y + DIRECTIONS[direction][1],
+        ];
+        if n_x < 0 || n_y < 0 || n_x >= self.dims[0] as i32 || n_y >= self.dims[1] as i32 {
+            return None;
+        }
+        return Some(n_x as usize * self.dims[1] + n_y as usize);
+    }
+
+    fn entropy(&self, cell: usize) -> f64 {
+        let sum = self.sum_weights[cell];
+        return sum.ln() - self.sum_weight_log_weights[cell] / sum;
+    }
+
+    fn ban(&mut self, generator: &WfcGenerator, cell: usize, pattern: usize) {
+        let idx = cell * self.num_patterns + pattern;
+        if !self.possible[idx] {
+            return;
+        }
+        self.possible[idx] = false;
+        self.remaining[cell] -= 1;
+        let weight = generator.weights[pattern];
+        self.sum_weights[cell] -= weight;
+        self.sum_weight_log_weights[cell] -= weight * weight.ln();
+        self.pending_bans.push((cell, pattern));
+        self.touched.push(cell);
+    }
+
+    /// Spread the consequences of every pending ban. Returns false on a contradiction.
+    fn propagate(&mut self, generator: &WfcGenerator) -> bool {
+        while let Some((cell, pattern)) = self.pending_bans.pop() {
+            if self.remaining[cell] == 0 {
+                return false;
+            }
+            for direction in 0..4 {
+                let Some(neighbor) = self.neighbor(cell, direction) else {
+                    continue;
+                };
+                for allowed in &generator.propagator[pattern][direction] {
+                    let support_idx = (neighbor * self.num_patterns + allowed) * 4 + direction;
+                    self.supports[support_idx] -= 1;
+                    if self.supports[support_idx] == 0 {
+                        self.ban(generator, neighbor, *allowed);
+                    }
+                }
+            }
+        }
+        return true;
+    }
+
+    fn collapse(mut self, generator: &WfcGenerator, rng: &mut StdRng) -> Option<Vec<usize>> {
+        let num_cells = self.dims[0] * self.dims[1];
+        // Patterns with no possible neighbor on an interior side can never be placed there.
+        for cell in 0..num_cells {
+            for direction in 0..4 {
+                if self.neighbor(cell, opposite(direction)).is_none() {
+                    continue;
+                }
+                for pattern in 0..self.num_patterns {
+                    if self.supports[(cell * self.num_patterns + pattern) * 4 + direction] == 0 {
+                        self.ban(generator, cell, pattern);
+                    }
+                }
+            }
+        }
+        if !self.propagate(generator) {
+            return None;
+        }
+        self.touched.clear();
+
+        // Lowest entropy first, with a little noise to break ties. Entries go stale as cells
+        // shrink, but entropy only ever falls, so a stale entry is still a fine pick.
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+        for cell in 0..num_cells {
+            let noisy_entropy = self.entropy(cell) + rng.random_range(0. ..1e-6);
+            heap.push(Reverse((noisy_entropy.max(0.).to_bits(), cell)));
+        }
+        while let Some(Reverse((_, cell))) = heap.pop() {
+            if self.remaining[cell] <= 1 {
+                continue;
+            }
+            let mut roll = rng.random_range(0. ..self.sum_weights[cell]);
+            let mut chosen = None;
+            for pattern in 0..self.num_patterns {
+                if !self.possible[cell * self.num_patterns + pattern] {
+                    continue;
+                }
+                chosen = Some(pattern);
+                roll -= generator.weights[pattern];
+                if roll < 0. {
+                    break;
+                }
+            }
+            let chosen = chosen.expect("A cell with patterns left has one to choose");
+            for pattern in 0..self.num_patterns {
+                if pattern != chosen {
+                    self.ban(generator, cell, pattern);
+                }
+            }
+            if !self.propagate(generator) {
+                return None;
+            }
+            for touched in std::mem::take(&mut self.touched) {
+                if self.remaining[touched] > 1 {
+                    let noisy_entropy = self.entropy(touched) + rng.random_range(0. ..1e-6);
+                    heap.push(Reverse((noisy_entropy.max(0.).to_bits(), touched)));
+                }
+            }
+        }
+
+        let mut chosen: Vec<usize> = Vec::with_capacity(num_cells);
+        for cell in 0..num_cells {
+            let pattern = (0..self.num_patterns)
+                .find(|pattern| self.possible[cell * self.num_patterns + pattern])?;
+            chosen.push(pattern);
+        }
+        return Some(chosen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn output_only_contains_patterns_from_the_example() {
+        for (name, _) in BUILT_IN_EXAMPLES {
+            let generator = WfcGenerator::built_in(name, 3).unwrap();
+            let game_map = Map::new(24, 20);
+            for seed in 0..5 {
+                let grid = generator
+                    .generate(
+                        &game_map,
+                        &MapGenerationOptions::default(),
+                        &mut StdRng::seed_from_u64(seed),
+                    )
+                    .unwrap();
+                for origin_x in 0..=(game_map.dimx() - 3) {
+                    for origin_y in 0..=(game_map.dimy() - 3) {
+                        let mut window: Vec<bool> = Vec::new();
+                        for offset_y in 0..3 {
+                            for offset_x in 0..3 {
+                                let item = grid[origin_x + offset_x][origin_y + offset_y];
+                                window.push(item == MapItem::Obstacle);
+                            }
+                        }
+                        assert!(
+                            generator.patterns.contains(&window),
+                            "{name}, seed {seed}: window at {origin_x},{origin_y}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}